@@ -0,0 +1,89 @@
+use std::collections::BTreeSet;
+
+use super::{subtract, Cube};
+use crate::automata::pda::{InputSymbol, StackSymbol};
+use crate::automata::tm::State;
+use crate::automata::{DeterministicFiniteAutomata, PushDownAutomata, TuringMachine};
+
+/// Symbol tuples a reachable, non-final state has no rule for.
+///
+/// Only symbols that may ever be on a tape are considered: the input symbols and
+/// the blank on tape 0, the blank elsewhere, plus whatever some rule writes there.
+pub fn tm(tm: &TuringMachine) -> Vec<(State, Vec<Cube>)> {
+    let mut universe: Cube = vec![BTreeSet::from([tm.B()]); tm.N()];
+    if let Some(tape) = universe.first_mut() {
+        tape.extend(tm.S());
+    }
+    for (_, (Y, _, _)) in tm.delta() {
+        for (tape, y) in universe.iter_mut().zip(Y) {
+            if *y != '*' {
+                tape.insert(*y);
+            }
+        }
+    }
+
+    let edges = super::tm_edges(tm);
+    let mut gaps = Vec::new();
+    for q in super::reachable(tm.q0(), &edges) {
+        if tm.F().contains(q) {
+            continue;
+        }
+        let mut missing = vec![universe.clone()];
+        for ((p, pats), _) in tm.delta() {
+            if p == q {
+                let cube = super::overlap::cube(tm, pats);
+                missing = missing.iter().flat_map(|m| subtract(m, &cube)).collect();
+            }
+        }
+        if !missing.is_empty() {
+            gaps.push((q.to_owned(), missing));
+        }
+    }
+    gaps
+}
+
+/// `(q, a, X)` triples with no rule, `a` is `None` for a non-final state at the end of input.
+///
+/// Only stack symbols that may ever be on top are considered: `z0` and whatever
+/// some rule pushes.
+pub fn pda(pda: &PushDownAutomata) -> Vec<(State, Option<InputSymbol>, StackSymbol)> {
+    let mut stack = BTreeSet::from([pda.z0()]);
+    for (_, (_, beta)) in pda.delta() {
+        stack.extend(beta);
+    }
+    let input: BTreeSet<InputSymbol> = pda.S().iter().copied().collect();
+
+    let q0 = pda.q0();
+    let edges = super::pda_edges(pda);
+    let mut gaps = Vec::new();
+    for q in super::reachable(&q0, &edges) {
+        let q = q.to_owned();
+        for X in stack.iter() {
+            for a in input.iter() {
+                if pda.get(&q, Some(*a), *X).is_none() {
+                    gaps.push((q.clone(), Some(*a), *X));
+                }
+            }
+            if !pda.F().contains(&q) && pda.get(&q, None, *X).is_none() {
+                gaps.push((q.clone(), None, *X));
+            }
+        }
+    }
+    gaps
+}
+
+/// `(q, a)` pairs a reachable state has no rule for, reading `a` there rejects.
+pub fn dfa(dfa: &DeterministicFiniteAutomata) -> Vec<(State, InputSymbol)> {
+    let input: BTreeSet<InputSymbol> = dfa.S().iter().copied().collect();
+    let edges = super::dfa_edges(dfa);
+    let mut gaps = Vec::new();
+    for q in super::reachable(dfa.q0(), &edges) {
+        let q = q.to_owned();
+        for a in input.iter() {
+            if dfa.get(&q, *a).is_none() {
+                gaps.push((q.clone(), *a));
+            }
+        }
+    }
+    gaps
+}
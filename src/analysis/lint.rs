@@ -0,0 +1,280 @@
+use std::collections::HashSet;
+
+use crate::automata::{DeterministicFiniteAutomata, PushDownAutomata, TuringMachine};
+use crate::parse::{Position, SourceMap, Warning};
+
+#[derive(Debug, Clone)]
+pub enum Lint {
+    UnreachableState(String),
+    /// not final and without outgoing transitions, every run entering it rejects
+    DeadEndState(String),
+    /// a TM halts as soon as it enters a final state, rules out of it never apply
+    DeadRule(String),
+    UnusedSymbol(char),
+    /// a repeat the parser already warned about
+    Repeat(Warning),
+    /// the rules taking precedence that match everything this one does
+    ShadowedRule(Vec<Position>),
+}
+
+impl std::fmt::Display for Lint {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Lint::UnreachableState(q) => {
+                write!(f, "state '{}' is unreachable from the initial state", q)
+            }
+            Lint::DeadEndState(q) => write!(
+                f,
+                "state '{}' is not final but has no outgoing transitions",
+                q
+            ),
+            Lint::DeadRule(q) => write!(
+                f,
+                "rule never applies, the machine halts on entering final state '{}'",
+                q
+            ),
+            Lint::UnusedSymbol(ch) => write!(f, "symbol '{}' is never used by any transition", ch),
+            Lint::Repeat(w) => write!(f, "{}", w),
+            Lint::ShadowedRule(by) => {
+                write!(f, "rule is shadowed by the rule")?;
+                if by.len() > 1 {
+                    write!(f, "s")?;
+                }
+                for (i, pos) in by.iter().enumerate() {
+                    let sep = if i == 0 { " at" } else { "," };
+                    write!(f, "{} line {}", sep, pos.line())?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+pub type Finding = (Position, Lint);
+
+/// The parser's warnings, as findings.
+fn repeats(source: &SourceMap) -> Vec<Finding> {
+    source
+        .warnings
+        .iter()
+        .map(|(pos, w)| (pos.clone(), Lint::Repeat(w.clone())))
+        .collect()
+}
+
+fn states<'a>(
+    source: &SourceMap,
+    Q: &HashSet<String>,
+    F: &HashSet<String>,
+    q0: &'a str,
+    edges: &[(&'a str, &'a str)],
+) -> Vec<Finding> {
+    let reached: HashSet<&str> = super::reachable(q0, edges).into_iter().collect();
+
+    let mut findings = Vec::new();
+    for q in Q {
+        if !reached.contains(q.as_str()) {
+            findings.push((source.item("Q", q), Lint::UnreachableState(q.to_owned())));
+        }
+        if !F.contains(q) && edges.iter().all(|(from, _)| from != q) {
+            findings.push((source.item("Q", q), Lint::DeadEndState(q.to_owned())));
+        }
+    }
+    findings
+}
+
+fn unused(source: &SourceMap, k: &str, G: &HashSet<char>, used: &HashSet<char>) -> Vec<Finding> {
+    G.difference(used)
+        .map(|ch| (source.item(k, &ch.to_string()), Lint::UnusedSymbol(*ch)))
+        .collect()
+}
+
+/// Shadowed rules, except the repeats the parser warned about.
+fn shadowed(source: &SourceMap, reports: Vec<super::overlap::Report>) -> Vec<Finding> {
+    let repeated: HashSet<usize> = source
+        .warnings
+        .iter()
+        .filter(|(_, w)| matches!(w, Warning::DuplicateRule(_)))
+        .map(|(pos, _)| pos.row)
+        .collect();
+    let mut findings = Vec::new();
+    for report in reports {
+        for shadowed in report.shadowed {
+            if repeated.contains(&source.rules[shadowed.rule].row) {
+                continue;
+            }
+            findings.push((
+                source.rules[shadowed.rule].clone(),
+                Lint::ShadowedRule(
+                    shadowed
+                        .by
+                        .iter()
+                        .map(|i| source.rules[*i].clone())
+                        .collect(),
+                ),
+            ));
+        }
+    }
+    findings
+}
+
+fn sorted(mut findings: Vec<Finding>) -> Vec<Finding> {
+    findings.sort_by_key(|(pos, lint)| (pos.row, pos.col, lint.to_string()));
+    findings
+}
+
+pub fn tm(tm: &TuringMachine) -> Vec<Finding> {
+    let source = tm.source();
+    let delta = tm.delta();
+    let mut findings = repeats(source);
+    findings.extend(shadowed(source, super::overlap::tm(tm)));
+
+    for (((q, _), _), pos) in delta.iter().zip(&source.rules) {
+        if tm.F().contains(q) {
+            findings.push((pos.clone(), Lint::DeadRule(q.to_owned())));
+        }
+    }
+
+    let edges = super::tm_edges(tm);
+    findings.extend(states(source, tm.Q(), tm.F(), tm.q0(), &edges));
+
+    let mut used: HashSet<char> = tm.S().clone();
+    used.insert(tm.B());
+    for ((_, X), (Y, _, _)) in delta {
+        used.extend(X.iter().flat_map(|pat| pat.symbols()));
+        used.extend(Y.iter().filter(|ch| **ch != '*'));
+    }
+    findings.extend(unused(source, "G", tm.G(), &used));
+
+    sorted(findings)
+}
+
+pub fn pda(pda: &PushDownAutomata) -> Vec<Finding> {
+    let source = pda.source();
+    let delta = pda.delta();
+    let mut findings = repeats(source);
+    findings.extend(shadowed(source, super::overlap::pda(pda)));
+
+    let q0 = pda.q0();
+    let edges = super::pda_edges(pda);
+    findings.extend(states(source, pda.Q(), pda.F(), &q0, &edges));
+
+    let input: HashSet<char> = delta
+        .iter()
+        .filter_map(|((_, a, _), _)| a.as_ref())
+        .flat_map(|a| a.symbols())
+        .collect();
+    findings.extend(unused(source, "S", pda.S(), &input));
+
+    let mut stack = HashSet::from([pda.z0()]);
+    for ((_, _, X), (_, beta)) in delta {
+        stack.extend(X.symbols());
+        stack.extend(beta);
+    }
+    findings.extend(unused(source, "G", pda.G(), &stack));
+
+    sorted(findings)
+}
+
+/// A DFA rejects a repeated `(q, a)` while parsing, so there are no repeated or shadowed
+/// rules to look for.
+pub fn dfa(dfa: &DeterministicFiniteAutomata) -> Vec<Finding> {
+    let source = dfa.source();
+    let mut findings = repeats(source);
+
+    let edges = super::dfa_edges(dfa);
+    findings.extend(states(source, dfa.Q(), dfa.F(), dfa.q0(), &edges));
+
+    let input: HashSet<char> = dfa.delta().iter().map(|((_, a), _)| *a).collect();
+    findings.extend(unused(source, "S", dfa.S(), &input));
+
+    sorted(findings)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn found(findings: Vec<Finding>) -> Vec<(usize, String)> {
+        findings
+            .into_iter()
+            .map(|(pos, lint)| (pos.line(), lint.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn tm_findings() {
+        let program = "\
+#Q = {q,r,lost,stop}
+#S = {a}
+#G = {a,x,_}
+#q0 = q
+#B = _
+#F = {r}
+#N = 1
+
+q * * r r
+q a a r r
+r a a r q
+";
+        let tm: TuringMachine = program.parse().unwrap();
+        assert_eq!(
+            found(super::tm(&tm)),
+            [
+                (
+                    1,
+                    "state 'lost' is not final but has no outgoing transitions".to_owned()
+                ),
+                (
+                    1,
+                    "state 'lost' is unreachable from the initial state".to_owned()
+                ),
+                (
+                    1,
+                    "state 'stop' is not final but has no outgoing transitions".to_owned()
+                ),
+                (
+                    1,
+                    "state 'stop' is unreachable from the initial state".to_owned()
+                ),
+                (3, "symbol 'x' is never used by any transition".to_owned()),
+                (10, "rule is shadowed by the rule at line 9".to_owned()),
+                (
+                    11,
+                    "rule never applies, the machine halts on entering final state 'r'".to_owned()
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn pda_findings() {
+        let program = "\
+#Q = {q,r}
+#S = {a,b}
+#G = {z,z,y}
+#q0 = q
+#z0 = z
+#F = {r}
+
+q a z r z
+q a z r z
+q {a,b} z r z
+";
+        let pda: PushDownAutomata = program.parse().unwrap();
+        assert_eq!(
+            found(super::pda(&pda)),
+            [
+                (
+                    3,
+                    "'z' is declared more than once in #G (first at line 3, col 7)".to_owned()
+                ),
+                (3, "symbol 'y' is never used by any transition".to_owned()),
+                (
+                    8,
+                    "rule is shadowed by the rules at line 9, line 10".to_owned()
+                ),
+                (9, "rule repeats the one at line 8".to_owned()),
+            ]
+        );
+    }
+}
@@ -0,0 +1,239 @@
+pub mod coverage;
+pub mod lint;
+pub mod overlap;
+
+use std::collections::{BTreeSet, HashMap, HashSet, VecDeque};
+
+use crate::automata::{DeterministicFiniteAutomata, PushDownAutomata, TuringMachine};
+
+/// Indices of the rules that take precedence over rule `j` of `len`: the earlier ones where
+/// the first matching rule wins (TMs), the later ones where the last one does (PDAs).
+pub fn ahead(len: usize, j: usize, last_wins: bool) -> std::ops::Range<usize> {
+    match last_wins {
+        true => j + 1..len,
+        false => 0..j,
+    }
+}
+
+/// Transitions between states a TM may actually take, it halts on entering a final state.
+pub fn tm_edges(tm: &TuringMachine) -> Vec<(&str, &str)> {
+    tm.delta()
+        .iter()
+        .filter(|((q, _), _)| !tm.F().contains(q))
+        .map(|((q, _), (_, _, p))| (q.as_str(), p.as_str()))
+        .collect()
+}
+
+pub fn dfa_edges(dfa: &DeterministicFiniteAutomata) -> Vec<(&str, &str)> {
+    dfa.delta()
+        .iter()
+        .map(|((q, _), p)| (q.as_str(), p.as_str()))
+        .collect()
+}
+
+pub fn pda_edges(pda: &PushDownAutomata) -> Vec<(&str, &str)> {
+    pda.delta()
+        .iter()
+        .map(|((q, _, _), (p, _))| (q.as_str(), p.as_str()))
+        .collect()
+}
+
+/// States reachable from `q0` along `edges`, in breadth-first order.
+pub fn reachable<'a>(q0: &'a str, edges: &[(&'a str, &'a str)]) -> Vec<&'a str> {
+    let mut reached = vec![q0];
+    let mut seen = HashSet::from([q0]);
+    let mut queue = VecDeque::from([q0]);
+    while let Some(q) = queue.pop_front() {
+        for (_, p) in edges.iter().filter(|(from, _)| *from == q) {
+            if seen.insert(*p) {
+                reached.push(*p);
+                queue.push_back(*p);
+            }
+        }
+    }
+    reached
+}
+
+/// Words of at most `max_len` symbols a DFA accepts, in shortlex order.
+///
+/// Rather than running the DFA on every word, this follows its rules one symbol at a time
+/// from `q0`, and only extends a word while its state can still reach a final state within
+/// the symbols left, so dead branches are never expanded.
+pub fn dfa_accepted(dfa: &DeterministicFiniteAutomata, max_len: usize) -> Vec<String> {
+    let edges = dfa_edges(dfa);
+    // the fewest symbols from each state to a final one, searching back from `F`
+    let mut distance: HashMap<&str, usize> = dfa.F().iter().map(|q| (q.as_str(), 0)).collect();
+    let mut queue: VecDeque<&str> = distance.keys().copied().collect();
+    while let Some(p) = queue.pop_front() {
+        let d = distance[p] + 1;
+        for (q, _) in edges.iter().filter(|(_, to)| *to == p) {
+            if !distance.contains_key(q) {
+                distance.insert(q, d);
+                queue.push_back(q);
+            }
+        }
+    }
+    let live = |q: &str, left: usize| distance.get(q).is_some_and(|d| *d <= left);
+
+    let mut symbols: Vec<char> = dfa.S().iter().copied().collect();
+    symbols.sort();
+    let mut accepted = Vec::new();
+    // the words of one length that may still lead to acceptance, each with its state
+    let mut layer = match live(dfa.q0(), max_len) {
+        true => vec![(String::new(), dfa.q0())],
+        false => Vec::new(),
+    };
+    for len in 0..=max_len {
+        accepted.extend(
+            layer
+                .iter()
+                .filter(|(_, q)| dfa.F().contains(*q))
+                .map(|(w, _)| w.clone()),
+        );
+        layer = layer
+            .iter()
+            .flat_map(|(w, q)| {
+                symbols.iter().filter_map(move |&a| match dfa.get(q, a) {
+                    Some((_, p)) if len < max_len && live(p, max_len - len - 1) => {
+                        Some((format!("{}{}", w, a), p))
+                    }
+                    _ => None,
+                })
+            })
+            .collect();
+    }
+    accepted
+}
+
+/// A set of symbol tuples, written as the product of one symbol set per tape.
+pub type Cube = Vec<BTreeSet<char>>;
+
+pub fn intersect(a: &Cube, b: &Cube) -> Option<Cube> {
+    let c: Cube = a.iter().zip(b).map(|(x, y)| x & y).collect();
+    match c.iter().any(|x| x.is_empty()) {
+        true => None,
+        false => Some(c),
+    }
+}
+
+/// `a \ b` as a list of disjoint cubes.
+pub fn subtract(a: &Cube, b: &Cube) -> Vec<Cube> {
+    if intersect(a, b).is_none() {
+        return vec![a.clone()];
+    }
+    let mut cubes = Vec::new();
+    for k in 0..a.len() {
+        let rest = &a[k] - &b[k];
+        if rest.is_empty() {
+            continue;
+        }
+        let cube = (0..a.len())
+            .map(|i| match i.cmp(&k) {
+                std::cmp::Ordering::Less => &a[i] & &b[i],
+                std::cmp::Ordering::Equal => rest.clone(),
+                std::cmp::Ordering::Greater => a[i].clone(),
+            })
+            .collect();
+        cubes.push(cube);
+    }
+    cubes
+}
+
+/// Some tuple in a non-empty cube.
+pub fn example(c: &Cube) -> String {
+    c.iter().map(|x| x.first().unwrap()).collect()
+}
+
+/// A cube in transition syntax, a tape with several symbols is written as `{a,b}`.
+pub fn show(c: &Cube) -> String {
+    c.iter()
+        .map(|x| match x.len() {
+            1 => x.iter().collect(),
+            _ => format!(
+                "{{{}}}",
+                x.iter()
+                    .map(|ch| ch.to_string())
+                    .collect::<Vec<_>>()
+                    .join(",")
+            ),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cube(tapes: &[&str]) -> Cube {
+        tapes.iter().map(|t| t.chars().collect()).collect()
+    }
+
+    fn tuples(c: &Cube) -> BTreeSet<String> {
+        c.iter()
+            .fold(BTreeSet::from([String::new()]), |tuples, symbols| {
+                tuples
+                    .iter()
+                    .flat_map(|t| symbols.iter().map(move |ch| format!("{}{}", t, ch)))
+                    .collect()
+            })
+    }
+
+    #[test]
+    fn subtract_disjoint() {
+        let a = cube(&["ab", "x"]);
+        assert_eq!(subtract(&a, &cube(&["c", "x"])), vec![a]);
+    }
+
+    #[test]
+    fn subtract_leaves_disjoint_pieces_of_the_rest() {
+        let a = cube(&["abc", "xy", "_1"]);
+        let b = cube(&["b", "yz", "1"]);
+        let pieces = subtract(&a, &b);
+        for (i, p) in pieces.iter().enumerate() {
+            assert!(intersect(p, &b).is_none());
+            for q in &pieces[i + 1..] {
+                assert!(intersect(p, q).is_none());
+            }
+        }
+        let rest: BTreeSet<String> = pieces.iter().flat_map(tuples).collect();
+        let expected: BTreeSet<String> = tuples(&a).difference(&tuples(&b)).cloned().collect();
+        assert_eq!(rest, expected);
+        assert_eq!(rest.len(), 12 - 1);
+    }
+
+    #[test]
+    fn subtract_everything() {
+        let a = cube(&["ab", "x"]);
+        assert!(subtract(&a, &cube(&["abc", "xy"])).is_empty());
+    }
+
+    /// Words over {a,b} with an even number of `a`s, and a state no word leaves.
+    const EVEN: &str = "\
+#Q = {even,odd,dead}
+#S = {a,b}
+#q0 = even
+#F = {even}
+
+even a odd
+even b even
+odd a even
+odd b odd
+dead a dead
+";
+
+    #[test]
+    fn dfa_accepted_matches_running_every_word() {
+        let dfa: DeterministicFiniteAutomata = EVEN.parse().unwrap();
+        let expected: Vec<String> = crate::cases::shortlex(&['a', 'b'], 4)
+            .filter(|w| w.matches('a').count() % 2 == 0)
+            .collect();
+        assert_eq!(dfa_accepted(&dfa, 4), expected);
+    }
+
+    #[test]
+    fn dfa_accepted_without_final_states_is_empty() {
+        let dfa: DeterministicFiniteAutomata =
+            EVEN.replace("#F = {even}", "#F = {dead}").parse().unwrap();
+        assert!(dfa_accepted(&dfa, 6).is_empty());
+    }
+}
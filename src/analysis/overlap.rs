@@ -0,0 +1,175 @@
+use std::collections::BTreeSet;
+
+use super::{example, intersect, subtract, Cube};
+use crate::automata::pattern::Pattern;
+use crate::automata::tm::{State, TuringMachine};
+use crate::automata::PushDownAutomata;
+
+/// Two rules of the same state that both match some symbol tuple, indices into `delta`,
+/// `first` is the one written first.
+#[derive(Debug, Clone)]
+pub struct Overlap {
+    pub first: usize,
+    pub second: usize,
+    pub example: String,
+}
+
+/// A rule that never applies, since the rules `by`, which take precedence, match everything
+/// it does.
+#[derive(Debug, Clone)]
+pub struct Shadowed {
+    pub rule: usize,
+    pub by: Vec<usize>,
+    /// `None` if the rule matches no tuple over `G` at all
+    pub example: Option<String>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Report {
+    pub state: State,
+    pub overlaps: Vec<Overlap>,
+    pub shadowed: Vec<Shadowed>,
+}
+
+/// The tuples over `G` a read pattern matches.
+pub fn cube(tm: &TuringMachine, pats: &[Pattern]) -> Cube {
+    pats.iter()
+        .map(|pat| {
+            tm.G()
+                .iter()
+                .filter(|g| tm.matches(pat, **g))
+                .copied()
+                .collect()
+        })
+        .collect()
+}
+
+/// The `(a, X)` pairs a PDA rule matches, an epsilon move reads `_`.
+pub fn pda_cube(pda: &PushDownAutomata, a: &Option<Pattern>, X: &Pattern) -> Cube {
+    let input = match a {
+        Some(a) => pda.S().iter().filter(|s| a.matches(**s)).copied().collect(),
+        None => BTreeSet::from(['_']),
+    };
+    let stack = pda.G().iter().filter(|g| X.matches(**g)).copied().collect();
+    vec![input, stack]
+}
+
+/// Overlapping and shadowed TM rules, grouped by state in the order of their first rule.
+pub fn tm(tm: &TuringMachine) -> Vec<Report> {
+    let rules: Vec<_> = tm
+        .delta()
+        .iter()
+        .map(|((q, pats), _)| (q, cube(tm, pats)))
+        .collect();
+    reports(&rules, false)
+}
+
+/// Overlapping and shadowed PDA rules, the last matching rule wins. A rule reading input
+/// never overlaps an epsilon move, though `get` prefers the former.
+pub fn pda(pda: &PushDownAutomata) -> Vec<Report> {
+    let rules: Vec<_> = pda
+        .delta()
+        .iter()
+        .map(|((q, a, X), _)| (q, pda_cube(pda, a, X)))
+        .collect();
+    reports(&rules, true)
+}
+
+fn reports(rules: &[(&State, Cube)], last_wins: bool) -> Vec<Report> {
+    let cubes: Vec<&Cube> = rules.iter().map(|(_, c)| c).collect();
+
+    let mut reports: Vec<Report> = Vec::new();
+    for (j, (q, _)) in rules.iter().enumerate() {
+        let mut overlaps = Vec::new();
+        let mut by = Vec::new();
+        let mut residual = vec![cubes[j].clone()];
+        for i in super::ahead(rules.len(), j, last_wins).filter(|i| rules[*i].0 == *q) {
+            if let Some(c) = intersect(cubes[i], cubes[j]) {
+                overlaps.push(Overlap {
+                    first: i.min(j),
+                    second: i.max(j),
+                    example: example(&c),
+                });
+                by.push(i);
+                residual = residual
+                    .iter()
+                    .flat_map(|r| subtract(r, cubes[i]))
+                    .collect();
+            }
+        }
+
+        let report = match reports.iter().position(|r| &r.state == *q) {
+            Some(k) => &mut reports[k],
+            None => {
+                reports.push(Report {
+                    state: (*q).to_owned(),
+                    ..Default::default()
+                });
+                reports.last_mut().unwrap()
+            }
+        };
+        if residual.iter().all(|r| r.iter().any(|x| x.is_empty())) {
+            report.shadowed.push(Shadowed {
+                rule: j,
+                by,
+                example: intersect(cubes[j], cubes[j]).map(|c| example(&c)),
+            });
+        }
+        report.overlaps.extend(overlaps);
+    }
+    reports
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TM: &str = "\
+#Q = {q,r}
+#S = {a,b}
+#G = {a,b,_}
+#q0 = q
+#B = _
+#F = {r}
+#N = 1
+
+q * * r r
+q a a r r
+q _ _ r r
+";
+
+    const PDA: &str = "\
+#Q = {q,r}
+#S = {a,b}
+#G = {z}
+#q0 = q
+#z0 = z
+#F = {r}
+
+q a z r z
+q {a,b} z r z
+";
+
+    #[test]
+    fn tm_rule_behind_a_wildcard_is_shadowed() {
+        let reports = tm(&TM.parse().unwrap());
+        assert_eq!(reports.len(), 1);
+        let shadowed: Vec<(usize, &[usize])> = reports[0]
+            .shadowed
+            .iter()
+            .map(|s| (s.rule, s.by.as_slice()))
+            .collect();
+        assert_eq!(shadowed, [(1, &[0][..])]);
+        // `*` matches any symbol but the blank
+        assert!(reports[0].overlaps.iter().all(|o| o.second != 2));
+    }
+
+    #[test]
+    fn pda_rule_before_a_class_is_shadowed() {
+        let reports = pda(&PDA.parse().unwrap());
+        assert_eq!(reports.len(), 1);
+        let shadowed = &reports[0].shadowed;
+        assert_eq!(shadowed.len(), 1);
+        assert_eq!((shadowed[0].rule, shadowed[0].by.as_slice()), (0, &[1][..]));
+    }
+}
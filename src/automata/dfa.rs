@@ -1 +1,492 @@
-pub enum Exception {}
+use std::{
+    collections::{HashSet, VecDeque},
+    str::FromStr,
+};
+
+use super::{Error, StepOutcome, Stuck};
+use crate::parse::{
+    self, check_decls, parse_all, sorted, valid_state_char, valid_symbol_char, Diagnostic,
+    Position, SourceMap, Value,
+};
+
+pub type State = String;
+pub type InputSymbol = char;
+pub(crate) type TransL = (State, InputSymbol);
+pub(crate) type Trans = (TransL, State);
+
+#[derive(Clone, Default, Debug)]
+pub struct DeterministicFiniteAutomata {
+    Q: HashSet<State>,
+    S: HashSet<InputSymbol>,
+    q0: State,
+    F: HashSet<State>,
+    /// in source order
+    delta: Vec<Trans>,
+    source: SourceMap,
+}
+
+impl DeterministicFiniteAutomata {
+    pub fn Q(&self) -> &HashSet<State> {
+        &self.Q
+    }
+    pub fn S(&self) -> &HashSet<InputSymbol> {
+        &self.S
+    }
+    pub fn q0(&self) -> &State {
+        &self.q0
+    }
+    pub fn F(&self) -> &HashSet<State> {
+        &self.F
+    }
+    pub(crate) fn delta(&self) -> &Vec<Trans> {
+        &self.delta
+    }
+    pub(crate) fn source(&self) -> &SourceMap {
+        &self.source
+    }
+
+    /// The rule for reading `a` in `q`, with its index into `delta`.
+    pub(crate) fn get(&self, q: &State, a: InputSymbol) -> Option<(usize, &State)> {
+        self.delta
+            .iter()
+            .enumerate()
+            .find(|(_, ((p, b), _))| p == q && *b == a)
+            .map(|(k, (_, r))| (k, r))
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum SpecError {
+    DeclMissing(Vec<String>),
+    DeclUnknown(String),
+    Type(String),
+    QChar(State, char),
+    SChar(char),
+    MultiCharSymbol(String),
+    q0NotInQ(State),
+    FNotSubsetQ(State),
+    TLen(Vec<String>),
+    TInvalidState(String),
+    TInvalidSymbol(String),
+    /// a second rule for the same state and symbol
+    TConflict(State, InputSymbol),
+}
+
+impl std::fmt::Display for SpecError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SpecError::DeclMissing(ks) => {
+                let ks: Vec<String> = ks.iter().map(|k| format!("#{}", k)).collect();
+                write!(f, "missing declaration of {}", ks.join(", "))
+            }
+            SpecError::DeclUnknown(k) => write!(f, "unknown declaration #{}", k),
+            SpecError::Type(k) => match k.as_str() {
+                "q0" => write!(f, "#q0 must be a single value, not a set"),
+                _ => write!(f, "#{} must be a set like {{a,b}}", k),
+            },
+            SpecError::QChar(q, ch) => write!(
+                f,
+                "'{}' cannot appear in state name '{}', use letters, digits and '_'",
+                ch, q
+            ),
+            SpecError::SChar(ch) => write!(f, "'{}' cannot be an input symbol", ch),
+            SpecError::MultiCharSymbol(s) => write!(f, "'{}' is not a single character", s),
+            SpecError::q0NotInQ(q) => write!(f, "initial state '{}' is not declared in #Q", q),
+            SpecError::FNotSubsetQ(q) => write!(f, "final state '{}' is not declared in #Q", q),
+            SpecError::TLen(t) => write!(f, "expected 3 fields, found {}", t.len()),
+            SpecError::TInvalidState(q) => write!(f, "state '{}' is not declared in #Q", q),
+            SpecError::TInvalidSymbol(a) => write!(f, "'{}' is not declared in #S", a),
+            SpecError::TConflict(q, a) => write!(
+                f,
+                "a second rule for reading '{}' in state '{}', a DFA allows only one",
+                a, q
+            ),
+        }
+    }
+}
+
+impl Diagnostic for SpecError {
+    fn code(&self) -> &'static str {
+        match self {
+            SpecError::DeclMissing(_) => "E0201",
+            SpecError::DeclUnknown(_) => "E0202",
+            SpecError::Type(_) => "E0203",
+            SpecError::QChar(..) => "E0204",
+            SpecError::SChar(_) => "E0205",
+            SpecError::MultiCharSymbol(_) => "E0207",
+            SpecError::q0NotInQ(_) => "E0208",
+            SpecError::FNotSubsetQ(_) => "E0209",
+            SpecError::TLen(_) => "E0301",
+            SpecError::TInvalidState(_) => "E0303",
+            SpecError::TInvalidSymbol(_) => "E0304",
+            SpecError::TConflict(..) => "E0309",
+        }
+    }
+}
+
+impl std::error::Error for SpecError {}
+
+#[derive(Debug, Clone)]
+pub enum ParseError {
+    Syntax(parse::ParseError),
+    Spec(SpecError),
+}
+
+impl std::fmt::Display for ParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ParseError::Syntax(e) => write!(f, "{}", e),
+            ParseError::Spec(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl Diagnostic for ParseError {
+    fn code(&self) -> &'static str {
+        match self {
+            ParseError::Syntax(e) => e.code(),
+            ParseError::Spec(e) => e.code(),
+        }
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+impl FromStr for DeterministicFiniteAutomata {
+    type Err = Vec<(Position, ParseError)>;
+
+    /// All errors are reported, sorted by position.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut dfa = Self::default();
+
+        let (mut c, errors) = parse_all(s, 3);
+        let mut errors: Vec<_> = errors
+            .into_iter()
+            .map(|(pos, e)| (pos, ParseError::Syntax(e)))
+            .collect();
+
+        let decl_items_ref = ["Q", "S", "q0", "F"];
+        let (missing, unknown) = check_decls(&c, &decl_items_ref, &[]);
+        for (pos, k) in unknown {
+            errors.push(spec(&pos, SpecError::DeclUnknown(k)));
+        }
+
+        dfa.source = SourceMap::new(&c);
+
+        // declarations the checks below cannot rely on
+        let mut unusable: HashSet<String> = missing.iter().chain(&c.broken).cloned().collect();
+        if !missing.is_empty() {
+            errors.push(spec(&Position::default(), SpecError::DeclMissing(missing)));
+        }
+        for k in decl_items_ref {
+            let Some((pos, v)) = c.store.remove(k) else {
+                continue;
+            };
+            match (k, v) {
+                ("Q" | "F", Value::Set(v)) => {
+                    for (pos, state) in v {
+                        if let Some((i, ch)) =
+                            state.char_indices().find(|(_, ch)| !valid_state_char(*ch))
+                        {
+                            let pos = Position {
+                                col: pos.col + i,
+                                len: ch.len_utf8(),
+                                ..pos
+                            };
+                            errors.push(spec(&pos, SpecError::QChar(state.clone(), ch)));
+                        }
+                        match k {
+                            "Q" => dfa.Q.insert(state),
+                            _ => dfa.F.insert(state),
+                        };
+                    }
+                }
+                ("S", Value::Set(v)) => {
+                    for (pos, symbol) in v {
+                        let mut chars = symbol.chars();
+                        match (chars.next(), chars.next()) {
+                            (Some(ch), None) if valid_symbol_char(ch) && ch != '_' => {
+                                dfa.S.insert(ch);
+                            }
+                            (Some(ch), None) => errors.push(spec(&pos, SpecError::SChar(ch))),
+                            _ => errors.push(spec(&pos, SpecError::MultiCharSymbol(symbol))),
+                        }
+                    }
+                }
+                ("q0", Value::Str(v)) => dfa.q0 = v,
+                _ => {
+                    errors.push(spec(&pos, SpecError::Type(k.to_owned())));
+                    unusable.insert(k.to_owned());
+                }
+            }
+        }
+
+        let usable = |ks: &[&str]| ks.iter().all(|k| !unusable.contains(*k));
+        if usable(&["q0", "Q"]) && !dfa.Q.contains(&dfa.q0) {
+            let pos = &dfa.source.decls["q0"];
+            errors.push(spec(pos, SpecError::q0NotInQ(dfa.q0.clone())));
+        }
+
+        if usable(&["F", "Q"]) {
+            for (pos, q) in dfa.source.items["F"]
+                .iter()
+                .filter(|(_, q)| !dfa.Q.contains(q))
+            {
+                errors.push(spec(pos, SpecError::FNotSubsetQ(q.to_owned())));
+            }
+        }
+
+        // rules are checked against the declarations, which have to be sound first
+        if unusable.is_empty() {
+            let mut seen = HashSet::new();
+            for (pos, t) in c.trans {
+                if let Err(e) = dfa.rule(pos, t, &mut seen) {
+                    errors.push(e);
+                }
+            }
+        }
+
+        match errors.is_empty() {
+            true => Ok(dfa),
+            false => Err(sorted(errors)),
+        }
+    }
+}
+
+fn spec(pos: &Position, e: SpecError) -> (Position, ParseError) {
+    (pos.clone(), ParseError::Spec(e))
+}
+
+impl DeterministicFiniteAutomata {
+    /// Check a rule and add it, `seen` holds the (state, symbol) pairs already taken.
+    fn rule(
+        &mut self,
+        pos: Position,
+        t: Vec<String>,
+        seen: &mut HashSet<(State, char)>,
+    ) -> Result<(), (Position, ParseError)> {
+        let [q, a, p] = &t[..] else {
+            return Err(spec(&pos, SpecError::TLen(t)));
+        };
+        for (k, state) in [(0, q), (2, p)] {
+            if !self.Q.contains(state) {
+                return Err(spec(
+                    &pos.field(k),
+                    SpecError::TInvalidState(state.to_owned()),
+                ));
+            }
+        }
+        let mut chars = a.chars();
+        let a = match (chars.next(), chars.next()) {
+            (Some(a), None) if self.S.contains(&a) => a,
+            _ => return Err(spec(&pos.field(1), SpecError::TInvalidSymbol(a.to_owned()))),
+        };
+        if !seen.insert((q.to_owned(), a)) {
+            return Err(spec(&pos.field(1), SpecError::TConflict(q.to_owned(), a)));
+        }
+        self.delta.push(((q.to_owned(), a), p.to_owned()));
+        self.source.rules.push(pos);
+        Ok(())
+    }
+}
+
+pub struct ArchState {
+    dfa: DeterministicFiniteAutomata,
+    step: usize,
+    state: State,
+    input: VecDeque<InputSymbol>,
+    /// index into `delta` of the rule the last step applied
+    rule: Option<usize>,
+    /// undo log of (state, rule, consumed symbol), `None` unless recording
+    history: Option<Vec<(State, Option<usize>, InputSymbol)>>,
+}
+
+impl ArchState {
+    pub fn new(dfa: DeterministicFiniteAutomata) -> Self {
+        let q0 = dfa.q0.clone();
+        ArchState {
+            dfa,
+            step: 0,
+            state: q0,
+            input: VecDeque::new(),
+            rule: None,
+            history: None,
+        }
+    }
+}
+
+impl super::ArchState for ArchState {
+    fn input(&mut self, s: &str) -> Result<(), Error> {
+        for (col, symbol) in s.chars().enumerate() {
+            if !self.dfa.S.contains(&symbol) {
+                return Err(Error::InvalidInput { symbol, col });
+            }
+        }
+        self.input = VecDeque::from_iter(s.chars());
+        Ok(())
+    }
+
+    fn state(&self) -> &str {
+        &self.state
+    }
+
+    fn steps(&self) -> usize {
+        self.step
+    }
+
+    fn heads(&self) -> Vec<char> {
+        vec![self.input.front().copied().unwrap_or('_')]
+    }
+
+    fn step(&mut self) -> StepOutcome {
+        let Some(a) = self.input.front().copied() else {
+            return match self.dfa.F().contains(&self.state) {
+                true => StepOutcome::Accept(None),
+                false => StepOutcome::Reject(None),
+            };
+        };
+        let Some((k, p)) = self.dfa.get(&self.state, a) else {
+            return StepOutcome::Stuck(Stuck::NoRule {
+                state: self.state.clone(),
+                heads: vec![a],
+            });
+        };
+        let p = p.clone();
+        self.input.pop_front();
+        if let Some(history) = &mut self.history {
+            history.push((self.state.clone(), self.rule, a));
+        }
+        self.state = p;
+        self.rule = Some(k);
+        self.step += 1;
+        StepOutcome::Continue
+    }
+
+    fn rule(&self) -> Option<&Position> {
+        self.rule.map(|k| &self.dfa.source().rules[k])
+    }
+
+    fn view(&self) -> super::View {
+        super::View::Dfa {
+            input: self.input.iter().collect(),
+        }
+    }
+
+    fn record(&mut self) {
+        self.history.get_or_insert_with(Vec::new);
+    }
+
+    fn back(&mut self) -> bool {
+        let Some((state, rule, a)) = self.history.as_mut().and_then(|h| h.pop()) else {
+            return false;
+        };
+        self.input.push_front(a);
+        self.state = state;
+        self.rule = rule;
+        self.step -= 1;
+        true
+    }
+}
+
+impl std::fmt::Display for ArchState {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "Step : {}", self.step)?;
+        writeln!(f, "State: {}", self.state)?;
+        let super::View::Dfa { input } = super::ArchState::view(self) else {
+            unreachable!()
+        };
+        writeln!(f, "Input: {}", input)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::automata::{run, ArchState as _, StepOutcome, Stuck};
+
+    const EVEN: &str = "\
+; words with an even number of a
+#Q = {even,odd}
+#S = {a,b}
+#q0 = even
+#F = {even}
+
+even a odd
+even b even
+odd a even
+odd b odd
+";
+
+    fn run_on(dfa: &DeterministicFiniteAutomata, input: &str) -> StepOutcome {
+        let mut arch_state = ArchState::new(dfa.clone());
+        arch_state.input(input).unwrap();
+        run(&mut arch_state, None, |_| ())
+    }
+
+    #[test]
+    fn parse_and_run() {
+        let dfa: DeterministicFiniteAutomata = EVEN.parse().unwrap();
+        assert_eq!(dfa.delta().len(), 4);
+        assert_eq!(
+            dfa.get(&"odd".to_owned(), 'a'),
+            Some((2, &"even".to_owned()))
+        );
+        assert!(run_on(&dfa, "").accepted());
+        assert!(run_on(&dfa, "abba").accepted());
+        assert_eq!(run_on(&dfa, "ab"), StepOutcome::Reject(None));
+    }
+
+    #[test]
+    fn missing_rule_is_stuck() {
+        let dfa: DeterministicFiniteAutomata = EVEN.replace("odd b odd\n", "").parse().unwrap();
+        assert!(matches!(
+            run_on(&dfa, "ab"),
+            StepOutcome::Stuck(Stuck::NoRule { .. })
+        ));
+    }
+
+    #[test]
+    fn undeclared_input() {
+        let mut arch_state = ArchState::new(EVEN.parse().unwrap());
+        let e = arch_state.input("abc").unwrap_err();
+        assert_eq!(
+            e,
+            crate::automata::Error::InvalidInput {
+                symbol: 'c',
+                col: 2
+            }
+        );
+    }
+
+    #[test]
+    fn errors() {
+        let program = "\
+#Q = {even,odd}
+#S = {a,bb}
+#q0 = even
+#F = {even,gone}
+#G = {x}
+
+even a odd
+even a even
+odd a nowhere
+odd c odd
+";
+        let errors = program.parse::<DeterministicFiniteAutomata>().unwrap_err();
+        let found: Vec<(usize, &str)> = errors
+            .iter()
+            .map(|(pos, e)| (pos.line(), e.code()))
+            .collect();
+        assert_eq!(
+            found,
+            [
+                (2, "E0207"),
+                (4, "E0209"),
+                (5, "E0202"),
+                (8, "E0309"),
+                (9, "E0303"),
+                (10, "E0304"),
+            ]
+        );
+    }
+}
@@ -1,18 +1,159 @@
 pub mod dfa;
+pub(crate) mod pattern;
 pub mod pda;
 pub mod tm;
 
-pub enum Exception {
-    Dfa(dfa::Exception),
-    Pda(pda::Exception),
-    Tm(tm::Exception),
+/// An input the machine cannot run on.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Error {
+    /// `symbol`, the `col`th (from 0) of the input, is not an input symbol
+    InvalidInput { symbol: char, col: usize },
+}
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Error::InvalidInput { symbol, col } => write!(
+                f,
+                "'{}' at column {} of the input is not declared in #S",
+                symbol,
+                col + 1
+            ),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+/// Why a machine halted without a rule to follow, which rejects.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Stuck {
+    /// no rule for the state and the symbols under the heads, with input left
+    NoRule { state: String, heads: Vec<char> },
+    /// a PDA emptied its stack
+    EmptyStack,
+}
+
+impl std::fmt::Display for Stuck {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Stuck::NoRule { state, heads } => write!(
+                f,
+                "no rule in state {} for {}",
+                state,
+                heads.iter().collect::<String>()
+            ),
+            Stuck::EmptyStack => write!(f, "the stack is empty"),
+        }
+    }
+}
+
+/// What a step did. A TM halts with its output, the content of tape 0.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum StepOutcome {
+    /// a rule applied, the machine goes on
+    Continue,
+    Accept(Option<String>),
+    Reject(Option<String>),
+    Stuck(Stuck),
+}
+
+impl StepOutcome {
+    pub fn halted(&self) -> bool {
+        *self != StepOutcome::Continue
+    }
+
+    pub fn accepted(&self) -> bool {
+        matches!(self, StepOutcome::Accept(_))
+    }
+
+    /// What the machine left on tape 0, for a halted TM.
+    pub fn output(&self) -> Option<&str> {
+        match self {
+            StepOutcome::Accept(s) | StepOutcome::Reject(s) => s.as_deref(),
+            _ => None,
+        }
+    }
+}
+
+impl std::fmt::Display for StepOutcome {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            StepOutcome::Continue => write!(f, "running"),
+            StepOutcome::Accept(_) => write!(f, "accepted"),
+            StepOutcome::Reject(_) => write!(f, "rejected"),
+            StepOutcome::Stuck(why) => write!(f, "rejected, {}", why),
+        }?;
+        match self.output() {
+            Some(s) => write!(f, ", result: {}", s),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Step a machine that has been given its input until it halts, or until it has taken
+/// `max_steps` steps and the outcome is `Continue`. `watch` sees the machine before every step.
+pub fn run(
+    arch_state: &mut dyn ArchState,
+    max_steps: Option<usize>,
+    mut watch: impl FnMut(&dyn ArchState),
+) -> StepOutcome {
+    while max_steps.is_none_or(|n| arch_state.steps() < n) {
+        watch(arch_state);
+        let outcome = arch_state.step();
+        if outcome.halted() {
+            return outcome;
+        }
+    }
+    StepOutcome::Continue
+}
+
+/// A tape as `Display` shows it, the cells from index `first` on with the head over `cells[head]`.
+pub struct Tape {
+    pub first: isize,
+    pub cells: Vec<char>,
+    pub head: usize,
+    /// the symbol the last step wrote under the head it had then
+    pub written: Option<char>,
+}
+
+/// The storage of a running machine, for front ends other than `Display`.
+pub enum View {
+    /// the remaining input
+    Dfa {
+        input: String,
+    },
+    Tm {
+        blank: char,
+        tapes: Vec<Tape>,
+    },
+    /// the remaining input, and the stack top first
+    Pda {
+        input: String,
+        stack: String,
+    },
 }
 
 pub trait ArchState: std::fmt::Display {
-    fn input(&mut self, s: &str) -> Result<(), Exception>;
-    fn step(&mut self) -> Result<(), Exception>;
+    fn input(&mut self, s: &str) -> Result<(), Error>;
+    /// Apply a rule, or halt. Stepping a halted machine halts it again the same way.
+    fn step(&mut self) -> StepOutcome;
+    fn state(&self) -> &str;
+    /// number of steps taken so far
+    fn steps(&self) -> usize;
+    /// symbol under each head, for a PDA the next input symbol and the stack top, `_` if none
+    fn heads(&self) -> Vec<char>;
+    /// where the rule the last step applied was declared
+    fn rule(&self) -> Option<&crate::parse::Position>;
+    fn view(&self) -> View;
+    /// Start keeping an undo log, so that `back` works.
+    fn record(&mut self);
+    /// Undo the last step, `false` if there is nothing recorded to undo.
+    fn back(&mut self) -> bool;
 }
 
+pub use dfa::ArchState as DfaArchState;
+pub use dfa::DeterministicFiniteAutomata;
 pub use pda::ArchState as PdaArchState;
 pub use pda::PushDownAutomata;
 pub use tm::ArchState as TmArchState;
@@ -0,0 +1,170 @@
+use std::collections::BTreeSet;
+
+/// What a transition expects to read from one tape cell, input or stack position.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Pattern {
+    Symbol(char),
+    /// `*`, whether it matches the blank symbol is up to the machine
+    Any,
+    /// `{a,b}` or `[ab]`, or every symbol but the listed ones with `{^a,b}` or `[^ab]`
+    Class {
+        negated: bool,
+        symbols: BTreeSet<char>,
+    },
+}
+
+impl Pattern {
+    /// Match against anything but `*`, which the machine has to decide on.
+    pub fn matches(&self, ch: char) -> bool {
+        match self {
+            Pattern::Symbol(s) => *s == ch,
+            Pattern::Any => true,
+            Pattern::Class { negated, symbols } => symbols.contains(&ch) != *negated,
+        }
+    }
+
+    /// The symbols the pattern names explicitly.
+    pub fn symbols(&self) -> Vec<char> {
+        match self {
+            Pattern::Symbol(s) => vec![*s],
+            Pattern::Any => vec![],
+            Pattern::Class { symbols, .. } => symbols.iter().copied().collect(),
+        }
+    }
+
+    /// Parse a single cell, `Err` carries the offending text.
+    pub fn parse(cell: &str) -> Result<Pattern, String> {
+        if let Some(class) = cell.strip_prefix('{') {
+            let Some(class) = class.strip_suffix('}') else {
+                return Err(cell.to_owned());
+            };
+            let (negated, class) = match class.strip_prefix('^') {
+                Some(class) => (true, class),
+                None => (false, class),
+            };
+            let mut symbols = BTreeSet::new();
+            for item in class.split(',') {
+                let item = item.trim();
+                let mut chars = item.chars();
+                match (chars.next(), chars.next()) {
+                    (Some(ch), None) => symbols.insert(ch),
+                    _ => return Err(item.to_owned()),
+                };
+            }
+            return Ok(Pattern::Class { negated, symbols });
+        }
+        if let Some(class) = bracket(cell) {
+            let (negated, class) = match class.strip_prefix('^') {
+                Some(class) if !class.is_empty() => (true, class),
+                _ => (false, class),
+            };
+            let symbols = class.chars().collect();
+            return Ok(Pattern::Class { negated, symbols });
+        }
+        let mut chars = cell.chars();
+        match (chars.next(), chars.next()) {
+            (Some('*'), None) => Ok(Pattern::Any),
+            (Some(ch), None) => Ok(Pattern::Symbol(ch)),
+            _ => Err(cell.to_owned()),
+        }
+    }
+}
+
+impl std::fmt::Display for Pattern {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Pattern::Symbol(s) => write!(f, "{}", s),
+            Pattern::Any => write!(f, "*"),
+            Pattern::Class { negated, symbols } => {
+                write!(f, "{{")?;
+                if *negated {
+                    write!(f, "^")?;
+                }
+                let symbols: Vec<_> = symbols.iter().map(|s| s.to_string()).collect();
+                write!(f, "{}}}", symbols.join(","))
+            }
+        }
+    }
+}
+
+/// The inside of a class `[...]`, without commas. `[` and `]` are ordinary symbols
+/// elsewhere, so `[]` is not a class.
+fn bracket(cell: &str) -> Option<&str> {
+    cell.strip_prefix('[')?
+        .strip_suffix(']')
+        .filter(|class| !class.is_empty())
+}
+
+/// Split a transition field into its cells, a class `{...}` or `[...]` counts as one cell.
+pub fn cells(field: &str) -> Vec<String> {
+    let mut cells = Vec::new();
+    let mut chars = field.chars();
+    while let Some(ch) = chars.next() {
+        let mut cell = ch.to_string();
+        // the nearest `]` past the next symbol closes a class
+        if ch == '[' {
+            if let Some(end) = chars.clone().skip(1).position(|ch| ch == ']') {
+                cell.extend(chars.by_ref().take(end + 2));
+            }
+        } else if ch == '{' {
+            for ch in chars.by_ref() {
+                cell.push(ch);
+                if ch == '}' {
+                    break;
+                }
+            }
+        }
+        cells.push(cell);
+    }
+    cells
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn class(negated: bool, symbols: &str) -> Pattern {
+        Pattern::Class {
+            negated,
+            symbols: symbols.chars().collect(),
+        }
+    }
+
+    #[test]
+    fn parse_classes() {
+        assert_eq!(Pattern::parse("[ab]"), Ok(class(false, "ab")));
+        assert_eq!(Pattern::parse("[^_x]"), Ok(class(true, "_x")));
+        assert_eq!(Pattern::parse("{a,b}"), Ok(class(false, "ab")));
+        assert_eq!(Pattern::parse("{^a, b}"), Ok(class(true, "ab")));
+        // a lone `^` is the symbol itself
+        assert_eq!(Pattern::parse("[^]"), Ok(class(false, "^")));
+        assert_eq!(Pattern::parse("*"), Ok(Pattern::Any));
+        assert_eq!(Pattern::parse("a"), Ok(Pattern::Symbol('a')));
+    }
+
+    #[test]
+    fn parse_errors() {
+        assert_eq!(Pattern::parse("{a,bc}"), Err("bc".to_owned()));
+        assert_eq!(Pattern::parse("{a,b"), Err("{a,b".to_owned()));
+        assert_eq!(Pattern::parse("[]"), Err("[]".to_owned()));
+    }
+
+    #[test]
+    fn negated_classes_match_the_rest() {
+        let p = class(true, "_x");
+        assert!(p.matches('a'));
+        assert!(!p.matches('x'));
+        assert!(!p.matches('_'));
+    }
+
+    #[test]
+    fn cells_keep_classes_whole() {
+        assert_eq!(cells("a[bc]*{d,e}"), ["a", "[bc]", "*", "{d,e}"]);
+        assert_eq!(cells("[^_x]_"), ["[^_x]", "_"]);
+        // `[` without a `]` at least one symbol later is a symbol
+        assert_eq!(cells("[]a"), ["[", "]", "a"]);
+        assert_eq!(cells("a["), ["a", "["]);
+        assert_eq!(cells("[]]"), ["[]]"]);
+        assert_eq!(cells("[éa]b"), ["[éa]", "b"]);
+    }
+}
@@ -1,19 +1,25 @@
 use std::{
-    collections::{HashMap, HashSet, VecDeque},
+    collections::{HashSet, VecDeque},
     str::FromStr,
 };
 
-use crate::parse::{self, parse, valid_state_char, valid_symbol_char, Position, Value};
+use super::pattern::Pattern;
+use super::{Error, StepOutcome, Stuck};
+use crate::parse::{
+    self, check_decls, parse_all, sorted, valid_state_char, valid_symbol_char, Diagnostic,
+    Position, SourceMap, Value,
+};
 
 pub type State = String;
 pub type InputSymbol = char;
 pub type StackSymbol = char;
 // stack top is on the right side
-pub type TransL = (State, Option<InputSymbol>, StackSymbol);
-pub type TransR = (State, Vec<StackSymbol>);
-pub type Trans = (TransL, TransR);
+/// an input pattern of `None` is an epsilon move
+pub(crate) type TransL = (State, Option<Pattern>, Pattern);
+pub(crate) type TransR = (State, Vec<StackSymbol>);
+pub(crate) type Trans = (TransL, TransR);
 
-#[derive(Default, Debug)]
+#[derive(Clone, Default, Debug)]
 pub struct PushDownAutomata {
     Q: HashSet<State>,
     S: HashSet<InputSymbol>,
@@ -21,7 +27,10 @@ pub struct PushDownAutomata {
     q0: State,
     z0: StackSymbol,
     F: HashSet<State>,
-    delta: HashMap<TransL, TransR>,
+    /// in source order, the last matching rule wins, as when a repeated `(q, a, X)`
+    /// replaced the earlier rule
+    delta: Vec<Trans>,
+    source: SourceMap,
 }
 
 impl PushDownAutomata {
@@ -44,237 +53,355 @@ impl PushDownAutomata {
     pub fn F(&self) -> &HashSet<State> {
         &self.F
     }
-    pub fn delta(&self) -> &HashMap<TransL, TransR> {
+    pub(crate) fn delta(&self) -> &Vec<Trans> {
         &self.delta
     }
-    pub fn get(
+    pub(crate) fn source(&self) -> &SourceMap {
+        &self.source
+    }
+    pub(crate) fn get(
         &self,
         q: &State,
         a: Option<InputSymbol>,
         X: StackSymbol,
-    ) -> Option<(Option<InputSymbol>, &(State, Vec<StackSymbol>))> {
-        let mut query = (q.clone(), a, X);
-        if let Some(r) = self.delta.get(&query) {
-            return Some((query.1, r));
-        }
-        if query.1.is_some() {
-            query.1 = None;
-        }
-        if let Some(r) = self.delta.get(&query) {
-            return Some((query.1, r));
+    ) -> Option<(usize, Option<InputSymbol>, &TransR)> {
+        let find = |a: Option<InputSymbol>| {
+            self.delta
+                .iter()
+                .enumerate()
+                .rev()
+                .find(|(_, ((p, b, Y), _))| {
+                    p == q
+                        && Y.matches(X)
+                        && match (b, a) {
+                            (Some(b), Some(a)) => b.matches(a),
+                            (None, None) => true,
+                            _ => false,
+                        }
+                })
+                .map(|(k, (_, r))| (k, r))
+        };
+        if a.is_some() {
+            if let Some((k, r)) = find(a) {
+                return Some((k, a, r));
+            }
         }
-        None
+        find(None).map(|(k, r)| (k, None, r))
     }
 }
 
 #[derive(Debug, Clone)]
-#[allow(dead_code)]
 pub enum SpecError {
-    DeclItem(HashSet<String>),
+    DeclMissing(Vec<String>),
+    DeclUnknown(String),
     Type(String),
     QChar(State, char),
     SChar(char),
     GChar(char),
     MultiCharSymbol(String),
-    q0NotInQ,
-    z0NotInG,
+    q0NotInQ(State),
+    z0NotInG(StackSymbol),
     TLen(Vec<String>),
     TInvalidState(String),
     TInvalidSymbol(char),
-    FNotSubsetQ,
+    TInvalidClass(String),
+    FNotSubsetQ(State),
+}
+
+impl std::fmt::Display for SpecError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SpecError::DeclMissing(ks) => {
+                let ks: Vec<String> = ks.iter().map(|k| format!("#{}", k)).collect();
+                write!(f, "missing declaration of {}", ks.join(", "))
+            }
+            SpecError::DeclUnknown(k) => write!(f, "unknown declaration #{}", k),
+            SpecError::Type(k) => match k.as_str() {
+                "Q" | "S" | "G" | "F" => write!(f, "#{} must be a set like {{a,b}}", k),
+                _ => write!(f, "#{} must be a single value, not a set", k),
+            },
+            SpecError::QChar(q, ch) => write!(
+                f,
+                "'{}' cannot appear in state name '{}', use letters, digits and '_'",
+                ch, q
+            ),
+            SpecError::SChar(ch) => write!(f, "'{}' cannot be an input symbol", ch),
+            SpecError::GChar(ch) => write!(f, "'{}' cannot be a stack symbol", ch),
+            SpecError::MultiCharSymbol(s) => write!(f, "'{}' is not a single character", s),
+            SpecError::q0NotInQ(q) => write!(f, "initial state '{}' is not declared in #Q", q),
+            SpecError::z0NotInG(z) => {
+                write!(f, "start stack symbol '{}' is not declared in #G", z)
+            }
+            SpecError::TLen(t) => write!(f, "expected 5 fields, found {}", t.len()),
+            SpecError::TInvalidState(q) => write!(f, "state '{}' is not declared in #Q", q),
+            SpecError::TInvalidSymbol('*') => {
+                write!(f, "'*' is not allowed in a PDA, use a symbol class")
+            }
+            SpecError::TInvalidSymbol('_') => {
+                write!(f, "'_' cannot be popped, every move pops one stack symbol")
+            }
+            SpecError::TInvalidSymbol(ch) => {
+                write!(f, "'{}' is not a declared input or stack symbol", ch)
+            }
+            SpecError::TInvalidClass(e) => write!(f, "invalid symbol class: {}", e),
+            SpecError::FNotSubsetQ(q) => write!(f, "final state '{}' is not declared in #Q", q),
+        }
+    }
 }
 
+impl Diagnostic for SpecError {
+    fn code(&self) -> &'static str {
+        match self {
+            SpecError::DeclMissing(_) => "E0201",
+            SpecError::DeclUnknown(_) => "E0202",
+            SpecError::Type(_) => "E0203",
+            SpecError::QChar(..) => "E0204",
+            SpecError::SChar(_) => "E0205",
+            SpecError::GChar(_) => "E0206",
+            SpecError::MultiCharSymbol(_) => "E0207",
+            SpecError::q0NotInQ(_) => "E0208",
+            SpecError::FNotSubsetQ(_) => "E0209",
+            SpecError::z0NotInG(_) => "E0212",
+            SpecError::TLen(_) => "E0301",
+            SpecError::TInvalidState(_) => "E0303",
+            SpecError::TInvalidSymbol(_) => "E0304",
+            SpecError::TInvalidClass(_) => "E0306",
+        }
+    }
+}
+
+impl std::error::Error for SpecError {}
+
 #[derive(Debug, Clone)]
-#[allow(dead_code)]
 pub enum ParseError {
     Syntax(parse::ParseError),
     Spec(SpecError),
 }
 
+impl std::fmt::Display for ParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ParseError::Syntax(e) => write!(f, "{}", e),
+            ParseError::Spec(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl Diagnostic for ParseError {
+    fn code(&self) -> &'static str {
+        match self {
+            ParseError::Syntax(e) => e.code(),
+            ParseError::Spec(e) => e.code(),
+        }
+    }
+}
+
+impl std::error::Error for ParseError {}
+
 impl FromStr for PushDownAutomata {
-    type Err = (Position, ParseError);
+    type Err = Vec<(Position, ParseError)>;
 
+    /// All errors are reported, sorted by position.
     fn from_str(s: &str) -> Result<Self, Self::Err> {
         let mut pda = Self::default();
 
-        let mut c = match parse(s, 5) {
-            Ok(c) => c,
-            Err((pos, e)) => return Err((pos, ParseError::Syntax(e))),
-        };
+        let (mut c, errors) = parse_all(s, 5);
+        let mut errors: Vec<_> = errors
+            .into_iter()
+            .map(|(pos, e)| (pos, ParseError::Syntax(e)))
+            .collect();
 
-        let decl_items_ref = HashSet::from(["Q", "S", "G", "q0", "z0", "F"]);
-        let decl_items_dut = c
-            .store
-            .iter()
-            .map(|kv| kv.0.as_str())
-            .collect::<HashSet<_>>();
-
-        if decl_items_dut != decl_items_ref {
-            return Err((
-                Position::default(),
-                ParseError::Spec(SpecError::DeclItem(
-                    decl_items_dut
-                        .symmetric_difference(&decl_items_ref)
-                        .map(|s| (*s).to_owned())
-                        .collect(),
-                )),
-            ));
+        let decl_items_ref = ["Q", "S", "G", "q0", "z0", "F"];
+        let (missing, unknown) = check_decls(&c, &decl_items_ref, &[]);
+        for (pos, k) in unknown {
+            errors.push(spec(&pos, SpecError::DeclUnknown(k)));
         }
 
+        pda.source = SourceMap::new(&c);
+
+        // declarations the checks below cannot rely on
+        let mut unusable: HashSet<String> = missing.iter().chain(&c.broken).cloned().collect();
+        if !missing.is_empty() {
+            errors.push(spec(&Position::default(), SpecError::DeclMissing(missing)));
+        }
         for k in decl_items_ref {
-            let (k, (pos, v)) = c.store.remove_entry(k).unwrap();
-            fn valid_states(states: &HashSet<String>) -> Result<(), ParseError> {
-                for state in states.iter() {
-                    for ch in state.chars() {
-                        if !valid_state_char(ch) {
-                            return Err(ParseError::Spec(SpecError::QChar(state.to_owned(), ch)));
+            let Some((pos, v)) = c.store.remove(k) else {
+                continue;
+            };
+            match (k, v) {
+                ("Q" | "F", Value::Set(v)) => {
+                    for (pos, state) in v {
+                        if let Some((i, ch)) =
+                            state.char_indices().find(|(_, ch)| !valid_state_char(*ch))
+                        {
+                            let pos = Position {
+                                col: pos.col + i,
+                                len: ch.len_utf8(),
+                                ..pos
+                            };
+                            errors.push(spec(&pos, SpecError::QChar(state.clone(), ch)));
                         }
+                        match k {
+                            "Q" => pda.Q.insert(state),
+                            _ => pda.F.insert(state),
+                        };
                     }
                 }
-                Ok(())
-            }
-            match k.as_str() {
-                "Q" | "S" | "G" | "F" => {
-                    if let Value::Set(v) = v {
-                        match k.as_str() {
-                            "Q" => {
-                                if let Err(e) = valid_states(&v) {
-                                    return Err((pos, e));
-                                }
-                                pda.Q = v;
-                            }
-                            "S" => {
-                                for symbol in v.iter() {
-                                    if symbol.len() != 1 {
-                                        return Err((
-                                            pos,
-                                            ParseError::Spec(SpecError::MultiCharSymbol(
-                                                symbol.to_owned(),
-                                            )),
-                                        ));
-                                    }
-                                    let ch = symbol.chars().nth(0).unwrap();
-                                    if !valid_symbol_char(ch) || ch == '_' {
-                                        return Err((pos, ParseError::Spec(SpecError::SChar(ch))));
-                                    }
-                                    pda.S.insert(ch);
-                                }
-                            }
-                            "G" => {
-                                for symbol in v.iter() {
-                                    if symbol.len() != 1 {
-                                        return Err((
-                                            pos,
-                                            ParseError::Spec(SpecError::MultiCharSymbol(
-                                                symbol.to_owned(),
-                                            )),
-                                        ));
-                                    }
-                                    let ch = symbol.chars().nth(0).unwrap();
-                                    if !valid_symbol_char(ch) || ch == '_' {
-                                        return Err((pos, ParseError::Spec(SpecError::GChar(ch))));
-                                    }
-                                    pda.G.insert(ch);
-                                }
-                            }
-                            "F" => {
-                                if let Err(e) = valid_states(&v) {
-                                    return Err((pos, e));
-                                }
-                                pda.F = v;
+                ("S", Value::Set(v)) => {
+                    for (pos, s) in v {
+                        match symbol(&pos, &s) {
+                            Ok(ch) if valid_symbol_char(ch) && ch != '_' => {
+                                pda.S.insert(ch);
                             }
-                            _ => panic!(),
+                            Ok(ch) => errors.push(spec(&pos, SpecError::SChar(ch))),
+                            Err(e) => errors.push(e),
                         }
-                    } else {
-                        return Err((pos, ParseError::Spec(SpecError::Type(k.to_owned()))));
                     }
                 }
-                "q0" | "z0" => {
-                    if let Value::Str(v) = v {
-                        match k.as_str() {
-                            "q0" => pda.q0 = v,
-                            "z0" => {
-                                if v.len() != 1 {
-                                    return Err((
-                                        pos,
-                                        ParseError::Spec(SpecError::MultiCharSymbol(v)),
-                                    ));
-                                }
-                                pda.z0 = v.chars().nth(0).unwrap();
+                ("G", Value::Set(v)) => {
+                    for (pos, s) in v {
+                        match symbol(&pos, &s) {
+                            Ok(ch) if valid_symbol_char(ch) && ch != '_' => {
+                                pda.G.insert(ch);
                             }
-                            _ => panic!(),
+                            Ok(ch) => errors.push(spec(&pos, SpecError::GChar(ch))),
+                            Err(e) => errors.push(e),
                         }
-                    } else {
-                        return Err((pos, ParseError::Spec(SpecError::Type(k.to_owned()))));
                     }
                 }
-                _ => panic!(),
+                ("q0", Value::Str(v)) => pda.q0 = v,
+                ("z0", Value::Str(v)) => match symbol(&pos, &v) {
+                    Ok(ch) => pda.z0 = ch,
+                    Err(e) => {
+                        errors.push(e);
+                        unusable.insert(k.to_owned());
+                    }
+                },
+                _ => {
+                    errors.push(spec(&pos, SpecError::Type(k.to_owned())));
+                    unusable.insert(k.to_owned());
+                }
             }
         }
 
-        if !pda.Q.contains(&pda.q0) {
-            return Err((Position::default(), ParseError::Spec(SpecError::q0NotInQ)));
+        let usable = |ks: &[&str]| ks.iter().all(|k| !unusable.contains(*k));
+        if usable(&["q0", "Q"]) && !pda.Q.contains(&pda.q0) {
+            let pos = &pda.source.decls["q0"];
+            errors.push(spec(pos, SpecError::q0NotInQ(pda.q0.clone())));
         }
 
-        if !pda.G.contains(&pda.z0) {
-            return Err((Position::default(), ParseError::Spec(SpecError::z0NotInG)));
+        if usable(&["z0", "G"]) && !pda.G.contains(&pda.z0) {
+            errors.push(spec(&pda.source.decls["z0"], SpecError::z0NotInG(pda.z0)));
         }
 
-        if !pda.F.is_subset(&pda.Q) {
-            return Err((
-                Position::default(),
-                ParseError::Spec(SpecError::FNotSubsetQ),
-            ));
+        if usable(&["F", "Q"]) {
+            for (pos, q) in pda.source.items["F"]
+                .iter()
+                .filter(|(_, q)| !pda.Q.contains(q))
+            {
+                errors.push(spec(pos, SpecError::FNotSubsetQ(q.to_owned())));
+            }
         }
 
-        for (pos, t) in c.trans {
-            if let [q, a, X, p, beta] = &t[..] {
-                for state in [p, q] {
-                    if !pda.Q.contains(state) {
-                        return Err((
-                            pos,
-                            ParseError::Spec(SpecError::TInvalidState(state.to_owned())),
-                        ));
-                    }
+        // rules are checked against the declarations, which have to be sound first
+        if unusable.is_empty() {
+            for (pos, t) in c.trans {
+                if let Err(e) = pda.rule(pos, t) {
+                    errors.push(e);
                 }
-                for ch in [a, X] {
-                    if ch.len() != 1 {
-                        return Err((
-                            pos,
-                            ParseError::Spec(SpecError::MultiCharSymbol(ch.to_owned())),
-                        ));
-                    }
+            }
+        }
+
+        match errors.is_empty() {
+            true => Ok(pda),
+            false => Err(sorted(errors)),
+        }
+    }
+}
+
+fn spec(pos: &Position, e: SpecError) -> (Position, ParseError) {
+    (pos.clone(), ParseError::Spec(e))
+}
+
+/// A set member or value that must be a single symbol.
+fn symbol(pos: &Position, s: &str) -> Result<char, (Position, ParseError)> {
+    let mut chars = s.chars();
+    match (chars.next(), chars.next()) {
+        (Some(ch), None) => Ok(ch),
+        _ => Err(spec(pos, SpecError::MultiCharSymbol(s.to_owned()))),
+    }
+}
+
+impl PushDownAutomata {
+    /// Check a rule and add it.
+    fn rule(&mut self, pos: Position, t: Vec<String>) -> Result<(), (Position, ParseError)> {
+        let [q, a, X, p, beta] = &t[..] else {
+            return Err(spec(&pos, SpecError::TLen(t)));
+        };
+        for (k, state) in [(0, q), (3, p)] {
+            if !self.Q.contains(state) {
+                return Err(spec(
+                    &pos.field(k),
+                    SpecError::TInvalidState(state.to_owned()),
+                ));
+            }
+        }
+        let mut fields = Vec::new();
+        for (k, ch) in [(1, a), (2, X)] {
+            match Pattern::parse(ch) {
+                Ok(Pattern::Any) => {
+                    return Err(spec(&pos.field(k), SpecError::TInvalidSymbol('*')))
                 }
-                let a = a.chars().nth(0).unwrap();
-                let a = match a {
-                    '_' => None,
-                    a => {
-                        if !pda.S.contains(&a) {
-                            return Err((pos, ParseError::Spec(SpecError::TInvalidSymbol(a))));
-                        }
-                        Some(a)
+                Ok(pat) => fields.push(pat),
+                Err(e) if ch.starts_with('{') || ch.starts_with('[') => {
+                    return Err(spec(&pos.field(k), SpecError::TInvalidClass(e)))
+                }
+                Err(_) => {
+                    return Err(spec(
+                        &pos.field(k),
+                        SpecError::MultiCharSymbol(ch.to_owned()),
+                    ))
+                }
+            }
+        }
+        let X = fields.pop().unwrap();
+        let a = match fields.pop().unwrap() {
+            Pattern::Symbol('_') => None,
+            a => {
+                for ch in a.symbols() {
+                    if !self.S.contains(&ch) {
+                        return Err(spec(&pos.symbol(&[1], ch), SpecError::TInvalidSymbol(ch)));
                     }
-                };
-                let X = X.chars().nth(0).unwrap();
-                if X == '_' {
-                    return Err((pos, ParseError::Spec(SpecError::TInvalidSymbol(X))));
                 }
-                let beta = match beta.as_str() {
-                    "_" => Vec::new(),
-                    beta => beta.to_owned().chars().collect(),
-                };
-                for ch in &beta {
-                    if !pda.G.contains(ch) {
-                        return Err((pos, ParseError::Spec(SpecError::TInvalidSymbol(*ch))));
+                Some(a)
+            }
+        };
+        match &X {
+            Pattern::Symbol('_') => {
+                return Err(spec(&pos.field(2), SpecError::TInvalidSymbol('_')));
+            }
+            Pattern::Class { symbols, .. } => {
+                for ch in symbols {
+                    if !self.G.contains(ch) {
+                        return Err(spec(&pos.symbol(&[2], *ch), SpecError::TInvalidSymbol(*ch)));
                     }
                 }
-                pda.delta.insert((q.to_owned(), a, X), (p.to_owned(), beta));
-            } else {
-                return Err((pos, ParseError::Spec(SpecError::TLen(t))));
             }
+            _ => (),
         }
-
-        Ok(pda)
+        let beta = match beta.as_str() {
+            "_" => Vec::new(),
+            beta => beta.to_owned().chars().collect(),
+        };
+        for ch in &beta {
+            if !self.G.contains(ch) {
+                return Err(spec(&pos.symbol(&[4], *ch), SpecError::TInvalidSymbol(*ch)));
+            }
+        }
+        self.delta
+            .push(((q.to_owned(), a, X), (p.to_owned(), beta)));
+        self.source.rules.push(pos);
+        Ok(())
     }
 }
 
@@ -284,13 +411,21 @@ pub struct ArchState {
     state: State,
     input: VecDeque<InputSymbol>,
     stack: VecDeque<StackSymbol>,
+    /// index into `delta` of the rule the last step applied
+    rule: Option<usize>,
+    /// undo log, `None` unless recording
+    history: Option<Vec<Undo>>,
 }
 
-#[derive(Debug, Clone)]
-pub(crate) enum Exception {
-    InvalidInput { col: usize },
-    Accept,
-    Reject,
+/// What a step took away: the previous state and rule, the input symbol consumed (if any), the
+/// stack symbol popped and how many were pushed in its place.
+#[derive(Clone, Debug)]
+struct Undo {
+    state: State,
+    rule: Option<usize>,
+    consumed: Option<InputSymbol>,
+    popped: StackSymbol,
+    pushed: usize,
 }
 
 impl ArchState {
@@ -303,53 +438,160 @@ impl ArchState {
             state: q0,
             input: VecDeque::new(),
             stack: VecDeque::from([z0]),
+            rule: None,
+            history: None,
         }
     }
 }
 
 impl super::ArchState for ArchState {
-    fn input(&mut self, s: &str) -> Result<(), super::Exception> {
-        for (col, ch) in s.chars().enumerate() {
-            if !self.pda.S.contains(&ch) {
-                return Err(super::Exception::Pda(Exception::InvalidInput { col }));
+    fn input(&mut self, s: &str) -> Result<(), Error> {
+        for (col, symbol) in s.chars().enumerate() {
+            if !self.pda.S.contains(&symbol) {
+                return Err(Error::InvalidInput { symbol, col });
             }
         }
         self.input = VecDeque::from_iter(s.chars());
         Ok(())
     }
 
-    fn step(&mut self) -> Result<(), super::Exception> {
+    fn state(&self) -> &str {
+        &self.state
+    }
+
+    fn steps(&self) -> usize {
+        self.step
+    }
+
+    fn heads(&self) -> Vec<char> {
+        vec![
+            self.input.front().copied().unwrap_or('_'),
+            self.stack.front().copied().unwrap_or('_'),
+        ]
+    }
+
+    fn step(&mut self) -> StepOutcome {
         let q = &self.state;
         if self.input.is_empty() && self.pda.F().contains(q) {
-            return Err(super::Exception::Pda(Exception::Accept));
+            return StepOutcome::Accept(None);
         }
         let a = self.input.front();
         let X = match self.stack.front() {
             Some(X) => *X,
-            None => return Err(super::Exception::Pda(Exception::Reject)),
+            None => return StepOutcome::Stuck(Stuck::EmptyStack),
         };
-        if let Some((used, (p, beta))) = self.pda.get(q, a.copied(), X) {
-            if used.is_some() {
-                self.input.pop_front();
+        if let Some((k, used, (p, beta))) = self.pda.get(q, a.copied(), X) {
+            let consumed = match used {
+                Some(_) => self.input.pop_front(),
+                None => None,
+            };
+            let popped = self.stack.pop_front().unwrap();
+            if let Some(history) = &mut self.history {
+                history.push(Undo {
+                    state: self.state.clone(),
+                    rule: self.rule,
+                    consumed,
+                    popped,
+                    pushed: beta.len(),
+                });
             }
-            self.stack.pop_front().unwrap();
             self.state = p.clone();
+            self.rule = Some(k);
             for ch in beta.iter().rev() {
                 self.stack.push_front(*ch);
             }
             self.step += 1;
-            Ok(())
+            StepOutcome::Continue
+        } else if self.input.is_empty() {
+            StepOutcome::Reject(None)
         } else {
-            Err(super::Exception::Pda(Exception::Reject))
+            StepOutcome::Stuck(Stuck::NoRule {
+                state: q.clone(),
+                heads: vec![a.copied().unwrap(), X],
+            })
         }
     }
+
+    fn rule(&self) -> Option<&Position> {
+        self.rule.map(|k| &self.pda.source().rules[k])
+    }
+
+    fn view(&self) -> super::View {
+        super::View::Pda {
+            input: self.input.iter().collect(),
+            stack: self.stack.iter().collect(),
+        }
+    }
+
+    fn record(&mut self) {
+        self.history.get_or_insert_with(Vec::new);
+    }
+
+    fn back(&mut self) -> bool {
+        let Some(undo) = self.history.as_mut().and_then(|h| h.pop()) else {
+            return false;
+        };
+        self.stack.drain(..undo.pushed);
+        self.stack.push_front(undo.popped);
+        if let Some(a) = undo.consumed {
+            self.input.push_front(a);
+        }
+        self.state = undo.state;
+        self.rule = undo.rule;
+        self.step -= 1;
+        true
+    }
 }
 
 impl std::fmt::Display for ArchState {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         writeln!(f, "Step : {}", self.step)?;
         writeln!(f, "State: {}", self.state)?;
-        writeln!(f, "Input: {}", self.input.iter().collect::<String>())?;
-        writeln!(f, "Stack: {}", self.stack.iter().collect::<String>())
+        let super::View::Pda { input, stack } = super::ArchState::view(self) else {
+            unreachable!()
+        };
+        writeln!(f, "Input: {}", input)?;
+        writeln!(f, "Stack: {}", stack)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::automata::ArchState as _;
+
+    const ANBN: &str = "\
+#Q = {q0,q1,q2,accept}
+#S = {a,b}
+#G = {1,z}
+#q0 = q0
+#z0 = z
+#F = {accept}
+
+q0 a z q1 1z
+q1 a 1 q1 11
+q1 b 1 q2 _
+q2 b 1 q2 _
+q2 _ z accept _
+";
+
+    #[test]
+    fn back_retraces_a_run() {
+        let mut arch_state = ArchState::new(ANBN.parse().unwrap());
+        arch_state.input("aabb").unwrap();
+        arch_state.record();
+        let mut seen = vec![arch_state.to_string()];
+        let outcome = loop {
+            let outcome = arch_state.step();
+            if outcome.halted() {
+                break outcome;
+            }
+            seen.push(arch_state.to_string());
+        };
+        assert!(outcome.accepted());
+        while let Some(snapshot) = seen.pop() {
+            assert_eq!(arch_state.to_string(), snapshot);
+            assert_eq!(arch_state.back(), !seen.is_empty());
+        }
     }
 }
@@ -1,13 +1,18 @@
 use std::collections::{HashSet, VecDeque};
 
-use crate::parse::{parse, valid_state_char, valid_symbol_char, Position, Value};
+use super::pattern::{cells, Pattern};
+use super::{Error, StepOutcome};
+use crate::parse::{
+    check_decls, parse_all, sorted, valid_state_char, valid_symbol_char, Diagnostic, Position,
+    SourceMap, Value,
+};
 
 pub type State = String;
 pub type InputSymbol = char;
 pub type TapeSymbol = char;
-pub type TransL = (State, Vec<TapeSymbol>);
-pub type TransR = (Vec<TapeSymbol>, Vec<Direction>, State);
-pub type DeltaType = Vec<(TransL, TransR)>;
+pub(crate) type TransL = (State, Vec<Pattern>);
+pub(crate) type TransR = (Vec<TapeSymbol>, Vec<Direction>, State);
+pub(crate) type DeltaType = Vec<(TransL, TransR)>;
 
 #[derive(Debug, Clone)]
 pub enum Direction {
@@ -43,6 +48,53 @@ impl std::fmt::Display for Direction {
     }
 }
 
+/// How `*` in transitions is read, the default follows the 2024 manual.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Dialect {
+    /// `*` in a read pattern matches the blank symbol too
+    pub glob_blank: bool,
+    /// `*` in a write pattern may pair with a concrete read symbol, and keeps it
+    pub glob_keep: bool,
+}
+
+impl std::str::FromStr for Dialect {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (glob_blank, glob_keep) = match s {
+            "2024" => (false, false),
+            "glob_blank" => (true, false),
+            "glob_keep" => (false, true),
+            "relaxed" => (true, true),
+            _ => {
+                return Err(format!(
+                    "unknown dialect '{}', expected one of 2024, glob_blank, glob_keep, relaxed",
+                    s
+                ))
+            }
+        };
+        Ok(Dialect {
+            glob_blank,
+            glob_keep,
+        })
+    }
+}
+
+impl std::fmt::Display for Dialect {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}",
+            match (self.glob_blank, self.glob_keep) {
+                (false, false) => "2024",
+                (true, false) => "glob_blank",
+                (false, true) => "glob_keep",
+                (true, true) => "relaxed",
+            }
+        )
+    }
+}
+
 #[derive(Clone, Debug, Default)]
 pub struct TuringMachine {
     N: usize,
@@ -52,11 +104,15 @@ pub struct TuringMachine {
     q0: State,
     B: TapeSymbol,
     F: HashSet<State>,
+    /// symbol variables, bound by a read pattern and reusable in the rest of the rule
+    V: HashSet<char>,
     delta: DeltaType,
+    dialect: Dialect,
+    source: SourceMap,
 }
 
 impl TuringMachine {
-    pub fn input_valid(&self, input: &str) -> Result<(), usize> {
+    pub(crate) fn input_valid(&self, input: &str) -> Result<(), usize> {
         for (i, ch) in input.chars().enumerate() {
             if !self.S.contains(&ch) {
                 return Err(i);
@@ -85,15 +141,27 @@ impl TuringMachine {
     pub fn F(&self) -> &HashSet<State> {
         &self.F
     }
-    pub fn delta(&self) -> &DeltaType {
+    pub(crate) fn delta(&self) -> &DeltaType {
         &self.delta
     }
+    pub(crate) fn source(&self) -> &SourceMap {
+        &self.source
+    }
 
-    pub fn get(&self, q: &State, content: &[TapeSymbol]) -> Option<TransR> {
+    /// Whether the read pattern `pat` matches the tape symbol `syn`.
+    pub(crate) fn matches(&self, pat: &Pattern, syn: TapeSymbol) -> bool {
+        match pat {
+            Pattern::Any => self.dialect.glob_blank || syn != self.B,
+            pat => pat.matches(syn),
+        }
+    }
+
+    /// The first rule that applies, with its index into `delta`.
+    pub(crate) fn get(&self, q: &State, content: &[TapeSymbol]) -> Option<(usize, TransR)> {
         if content.len() != self.N {
             return None;
         }
-        'outer: for t @ ((_, ots), (nts, dirs, p)) in &self.delta {
+        'outer: for (k, t @ ((_, ots), (nts, dirs, p))) in self.delta.iter().enumerate() {
             if &t.0 .0 != q {
                 continue;
             }
@@ -101,291 +169,544 @@ impl TuringMachine {
             rnts.reserve_exact(self.N);
 
             for i in 0..self.N {
-                let pat = ots[i];
+                let pat = &ots[i];
                 let syn = content[i];
                 let nsyn = nts[i];
 
-                // if pat == '*' {         // always match
-                //     if syn == '_' {
-                //         continue 'outer;
-                //     }
-                // } else {                // try match
-                //     if syn != pat {
-                //         continue 'outer;
-                //     }
-                // }
-
-                // // pass matching
-
-                // if nsyn == '*' {        // don't change
-                //     rnts.push(syn);
-                // } else {                // change
-                //     rnts.push(nsyn);
-                // }
-
-                if pat == '*' {
-                    if syn == self.B {
-                        continue 'outer;
-                    } else if nsyn == '*' {
-                        rnts.push(syn);
-                    } else {
-                        rnts.push(nsyn);
-                    }
-                } else if pat == syn {
-                    if nsyn == '*' {
-                        rnts.push(syn);
-                    } else {
-                        rnts.push(nsyn);
-                    }
-                } else {
+                if !self.matches(pat, syn) {
                     continue 'outer;
                 }
+                if nsyn == '*' {
+                    rnts.push(syn);
+                } else {
+                    rnts.push(nsyn);
+                }
             }
 
-            return Some((rnts, dirs.clone(), p.clone()));
+            return Some((k, (rnts, dirs.clone(), p.clone())));
         }
         None
     }
 }
 
 #[derive(Debug, Clone)]
-#[allow(dead_code)]
-pub(crate) enum SpecError {
-    DeclItem(HashSet<String>),
+pub enum SpecError {
+    DeclMissing(Vec<String>),
+    DeclUnknown(String),
     MultiCharSymbol(String),
     QChar(char),
     GChar(char),
     SChar(char),
     Type(String),
-    FNotSubsetQ,
-    SNotSubsetG,
-    BNotInG,
-    q0NotInQ,
+    FNotSubsetQ(State),
+    SNotSubsetG(InputSymbol),
+    BNotInG(TapeSymbol),
+    q0NotInQ(State),
     TLen(Vec<String>),
-    TtsLen(Vec<String>),
+    /// the number of tapes
+    TtsLen(usize),
     TInvalidState(String),
     TInvalidSymbol(char),
-    TInvalidDirection,
-    TGlob(String, String),
+    TInvalidDirection(char),
+    TInvalidClass(String),
+    /// the concrete read symbol a `*` write is paired with
+    TGlob(String),
+    TUnboundVar(char),
+    /// a variable whose every capture is ruled out, so the rule would expand to nothing
+    TVarEmpty(char),
+    Dialect(String),
+    VChar(char),
 }
 
+impl std::fmt::Display for SpecError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SpecError::DeclMissing(ks) => {
+                let ks: Vec<String> = ks.iter().map(|k| format!("#{}", k)).collect();
+                write!(f, "missing declaration of {}", ks.join(", "))
+            }
+            SpecError::DeclUnknown(k) => write!(f, "unknown declaration #{}", k),
+            SpecError::MultiCharSymbol(s) => write!(f, "'{}' is not a single character", s),
+            SpecError::QChar(ch) => write!(
+                f,
+                "'{}' cannot appear in a state name, use letters, digits and '_'",
+                ch
+            ),
+            SpecError::GChar(ch) => write!(f, "'{}' cannot be a tape symbol", ch),
+            SpecError::SChar(ch) => write!(f, "'{}' cannot be an input symbol", ch),
+            SpecError::Type(k) => match k.as_str() {
+                "N" => write!(f, "#N must be the number of tapes"),
+                "Q" | "S" | "G" | "F" | "V" => write!(f, "#{} must be a set like {{a,b}}", k),
+                _ => write!(f, "#{} must be a single value, not a set", k),
+            },
+            SpecError::FNotSubsetQ(q) => write!(f, "final state '{}' is not declared in #Q", q),
+            SpecError::SNotSubsetG(a) => {
+                write!(f, "input symbol '{}' is not declared in #G", a)
+            }
+            SpecError::BNotInG(B) => write!(f, "blank symbol '{}' is not declared in #G", B),
+            SpecError::q0NotInQ(q) => write!(f, "initial state '{}' is not declared in #Q", q),
+            SpecError::TLen(t) => write!(f, "expected 5 fields, found {}", t.len()),
+            SpecError::TtsLen(1) => write!(f, "expected 1 symbol, for the only tape"),
+            SpecError::TtsLen(N) => write!(f, "expected {} symbols, one per tape", N),
+            SpecError::TInvalidState(q) => write!(f, "state '{}' is not declared in #Q", q),
+            SpecError::TInvalidSymbol(ch) => {
+                write!(f, "symbol '{}' is not declared in #G", ch)
+            }
+            SpecError::TInvalidDirection(d) => {
+                write!(f, "'{}' is not a direction, expected 'l', 'r' or '*'", d)
+            }
+            SpecError::TInvalidClass(e) => write!(f, "invalid symbol class: {}", e),
+            SpecError::TGlob(X) => write!(
+                f,
+                "'*' written over the concrete symbol '{}', \
+                 which needs `#dialect = glob_keep` or `relaxed`",
+                X
+            ),
+            SpecError::TUnboundVar(v) => {
+                write!(f, "variable '{}' is used before a read pattern binds it", v)
+            }
+            SpecError::TVarEmpty(v) => write!(
+                f,
+                "variable '{}' captures no symbol of #G, so the rule never applies \
+                 (as a direction it only captures 'l', 'r' and '*')",
+                v
+            ),
+            SpecError::Dialect(d) => write!(
+                f,
+                "unknown dialect '{}', expected one of 2024, glob_blank, glob_keep, relaxed",
+                d
+            ),
+            SpecError::VChar(ch) => write!(
+                f,
+                "'{}' cannot be a variable, it must not be in #G or be 'l' or 'r'",
+                ch
+            ),
+        }
+    }
+}
+
+impl Diagnostic for SpecError {
+    fn code(&self) -> &'static str {
+        match self {
+            SpecError::DeclMissing(_) => "E0201",
+            SpecError::DeclUnknown(_) => "E0202",
+            SpecError::Type(_) => "E0203",
+            SpecError::QChar(_) => "E0204",
+            SpecError::SChar(_) => "E0205",
+            SpecError::GChar(_) => "E0206",
+            SpecError::MultiCharSymbol(_) => "E0207",
+            SpecError::q0NotInQ(_) => "E0208",
+            SpecError::FNotSubsetQ(_) => "E0209",
+            SpecError::SNotSubsetG(_) => "E0210",
+            SpecError::BNotInG(_) => "E0211",
+            SpecError::Dialect(_) => "E0213",
+            SpecError::VChar(_) => "E0214",
+            SpecError::TLen(_) => "E0301",
+            SpecError::TtsLen(_) => "E0302",
+            SpecError::TInvalidState(_) => "E0303",
+            SpecError::TInvalidSymbol(_) => "E0304",
+            SpecError::TInvalidDirection(_) => "E0305",
+            SpecError::TInvalidClass(_) => "E0306",
+            SpecError::TGlob(_) => "E0307",
+            SpecError::TUnboundVar(_) => "E0308",
+            SpecError::TVarEmpty(_) => "E0310",
+        }
+    }
+}
+
+impl std::error::Error for SpecError {}
+
 #[derive(Debug, Clone)]
-#[allow(dead_code)]
 pub enum ParseError {
     Syntax(crate::parse::ParseError),
     Spec(SpecError),
 }
 
+impl std::fmt::Display for ParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ParseError::Syntax(e) => write!(f, "{}", e),
+            ParseError::Spec(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl Diagnostic for ParseError {
+    fn code(&self) -> &'static str {
+        match self {
+            ParseError::Syntax(e) => e.code(),
+            ParseError::Spec(e) => e.code(),
+        }
+    }
+}
+
+impl std::error::Error for ParseError {}
+
 impl std::str::FromStr for TuringMachine {
-    type Err = (Position, ParseError);
+    type Err = Vec<(Position, ParseError)>;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::parse_with(s, None)
+    }
+}
+
+fn spec(pos: &Position, e: SpecError) -> (Position, ParseError) {
+    (pos.clone(), ParseError::Spec(e))
+}
+
+/// A set member or value that must be a single symbol.
+fn symbol(pos: &Position, s: &str) -> Result<char, (Position, ParseError)> {
+    let mut chars = s.chars();
+    match (chars.next(), chars.next()) {
+        (Some(ch), None) => Ok(ch),
+        _ => Err(spec(pos, SpecError::MultiCharSymbol(s.to_owned()))),
+    }
+}
+
+impl TuringMachine {
+    /// Parse a TM description, `dialect` overrides its `#dialect` declaration.
+    /// All errors are reported, sorted by position.
+    pub fn parse_with(
+        s: &str,
+        dialect: Option<Dialect>,
+    ) -> Result<Self, Vec<(Position, ParseError)>> {
         let mut tm = TuringMachine::default();
-        let mut c = match parse(s, 5) {
-            Ok(c) => c,
-            Err(e) => return Err((e.0, ParseError::Syntax(e.1))),
-        };
+        let (mut c, errors) = parse_all(s, 5);
+        let mut errors: Vec<_> = errors
+            .into_iter()
+            .map(|(pos, e)| (pos, ParseError::Syntax(e)))
+            .collect();
 
-        let decl_items_ref = HashSet::from(["N", "Q", "S", "G", "q0", "B", "F"]);
-        let decl_items_dut = c
-            .store
-            .iter()
-            .map(|kv| kv.0.as_str())
-            .collect::<HashSet<_>>();
-
-        let pos = Position::default();
-
-        if decl_items_dut != decl_items_ref {
-            return Err((
-                pos,
-                ParseError::Spec(SpecError::DeclItem(
-                    decl_items_dut
-                        .symmetric_difference(&decl_items_ref)
-                        .map(|s| (*s).to_owned())
-                        .collect(),
-                )),
-            ));
-        }
-
-        for k in decl_items_ref {
-            let (k, (pos, v)) = c.store.remove_entry(k).unwrap();
-            fn valid_states(states: &HashSet<String>) -> Result<(), ParseError> {
-                for state in states.iter() {
-                    for ch in state.chars() {
-                        if !valid_state_char(ch) {
-                            return Err(ParseError::Spec(SpecError::QChar(ch)));
-                        }
-                    }
-                }
-                Ok(())
-            }
-            match k.as_str() {
-                "N" | "B" | "q0" => {
-                    if let Value::Str(v) = v {
-                        match k.as_str() {
-                            "N" => {
-                                tm.N = match v.parse::<usize>() {
-                                    Ok(v) => v,
-                                    Err(_) => {
-                                        return Err((pos, ParseError::Spec(SpecError::Type(v))))
-                                    }
-                                };
-                            }
-                            "B" => {
-                                if v.len() != 1 {
-                                    return Err((
-                                        pos,
-                                        ParseError::Spec(SpecError::MultiCharSymbol(v)),
-                                    ));
-                                }
-                                let ch = v.chars().nth(0).unwrap();
-                                if !valid_symbol_char(ch) {
-                                    return Err((pos, ParseError::Spec(SpecError::GChar(ch))));
-                                }
-                                tm.B = ch;
-                            }
-                            "q0" => {
-                                tm.q0 = v;
-                            }
-                            _ => panic!(),
-                        }
-                    } else {
-                        return Err((pos, ParseError::Spec(SpecError::Type(k.to_owned()))));
-                    }
-                }
-                "Q" | "S" | "G" | "F" => {
-                    if let Value::Set(v) = v {
-                        match k.as_str() {
-                            "Q" => {
-                                if let Err(e) = valid_states(&v) {
-                                    return Err((pos, e));
-                                }
-                                tm.Q = v;
-                            }
-                            "S" => {
-                                for symbol in v.iter() {
-                                    if symbol.len() != 1 {
-                                        return Err((
-                                            pos,
-                                            ParseError::Spec(SpecError::MultiCharSymbol(
-                                                symbol.to_owned(),
-                                            )),
-                                        ));
-                                    }
-                                    let ch = symbol.chars().nth(0).unwrap();
-                                    if !valid_symbol_char(ch) || ch == '_' {
-                                        return Err((pos, ParseError::Spec(SpecError::SChar(ch))));
-                                    }
-                                    tm.S.insert(ch);
-                                }
-                            }
-                            "G" => {
-                                for symbol in v.iter() {
-                                    if symbol.len() != 1 {
-                                        return Err((
-                                            pos,
-                                            ParseError::Spec(SpecError::MultiCharSymbol(
-                                                symbol.to_owned(),
-                                            )),
-                                        ));
-                                    }
-                                    let B = symbol.chars().nth(0).unwrap();
-                                    if !valid_symbol_char(B) {
-                                        return Err((pos, ParseError::Spec(SpecError::GChar(B))));
-                                    }
-                                    tm.G.insert(B);
-                                }
-                            }
-                            "F" => {
-                                if let Err(e) = valid_states(&v) {
-                                    return Err((pos, e));
-                                }
-                                tm.F = v;
-                            }
-                            _ => panic!(),
-                        }
-                    } else {
-                        return Err((pos, ParseError::Spec(SpecError::Type(k.to_owned()))));
-                    }
+        let decl_items_ref = ["N", "Q", "S", "G", "q0", "B", "F"];
+        let (missing, unknown) = check_decls(&c, &decl_items_ref, &["dialect", "V"]);
+        for (pos, k) in unknown {
+            errors.push(spec(&pos, SpecError::DeclUnknown(k)));
+        }
+
+        tm.source = SourceMap::new(&c);
+
+        // declarations the checks below cannot rely on
+        let mut unusable: HashSet<String> = missing.iter().chain(&c.broken).cloned().collect();
+        if !missing.is_empty() {
+            errors.push(spec(&Position::default(), SpecError::DeclMissing(missing)));
+        }
+        for k in decl_items_ref.into_iter().chain(["dialect", "V"]) {
+            if let Some((pos, v)) = c.store.remove(k) {
+                if !tm.decl(k, &pos, v, &mut errors) {
+                    unusable.insert(k.to_owned());
                 }
-                _ => panic!(),
             }
         }
+        if let Some(d) = dialect {
+            tm.dialect = d;
+            unusable.remove("dialect");
+        }
 
         if tm.B != '_' {
             log::warn!("The blank character B is '{}', not '_'!", tm.B);
         }
 
-        if !tm.Q.contains(&tm.q0) {
-            return Err((pos, ParseError::Spec(SpecError::q0NotInQ)));
+        let usable = |ks: &[&str]| ks.iter().all(|k| !unusable.contains(*k));
+        if usable(&["q0", "Q"]) && !tm.Q.contains(&tm.q0) {
+            let pos = &tm.source.decls["q0"];
+            errors.push(spec(pos, SpecError::q0NotInQ(tm.q0.clone())));
         }
 
-        if !tm.G.contains(&tm.B) {
-            return Err((pos, ParseError::Spec(SpecError::BNotInG)));
+        if usable(&["B", "G"]) && !tm.G.contains(&tm.B) {
+            errors.push(spec(&tm.source.decls["B"], SpecError::BNotInG(tm.B)));
         }
 
-        if !tm.F.is_subset(&tm.Q) {
-            return Err((pos, ParseError::Spec(SpecError::FNotSubsetQ)));
+        if usable(&["F", "Q"]) {
+            for (pos, q) in tm.source.items["F"]
+                .iter()
+                .filter(|(_, q)| !tm.Q.contains(q))
+            {
+                errors.push(spec(pos, SpecError::FNotSubsetQ(q.to_owned())));
+            }
         }
 
-        if !tm.S.is_subset(&tm.G) {
-            return Err((pos, ParseError::Spec(SpecError::SNotSubsetG)));
+        if usable(&["S", "G"]) {
+            // members that are not single symbols were reported already
+            for (pos, a) in &tm.source.items["S"] {
+                let mut chars = a.chars();
+                if let (Some(a), None) = (chars.next(), chars.next()) {
+                    if !tm.G.contains(&a) {
+                        errors.push(spec(pos, SpecError::SNotSubsetG(a)));
+                    }
+                }
+            }
         }
 
-        for (pos, t) in c.trans {
-            if let [q, X_str, Y_str, D, p] = &t[..] {
-                if X_str.len() != tm.N || Y_str.len() != tm.N || D.len() != tm.N {
-                    return Err((pos, ParseError::Spec(SpecError::TtsLen(t))));
+        // rules are checked against the declarations, which have to be sound first
+        if unusable.is_empty() {
+            for (pos, t) in c.trans {
+                if let Err(e) = tm.rule(&pos, t) {
+                    errors.push(e);
                 }
-                if !tm.Q.contains(q) {
-                    return Err((
-                        pos,
-                        ParseError::Spec(SpecError::TInvalidState(q.to_owned())),
-                    ));
+            }
+        }
+
+        match errors.is_empty() {
+            true => Ok(tm),
+            false => Err(sorted(errors)),
+        }
+    }
+
+    /// Read declaration `k`, `false` if it is unusable. Malformed set members are
+    /// reported and the others kept.
+    fn decl(
+        &mut self,
+        k: &str,
+        pos: &Position,
+        v: Value,
+        errors: &mut Vec<(Position, ParseError)>,
+    ) -> bool {
+        match (k, v) {
+            ("N", Value::Str(v)) => match v.parse::<usize>() {
+                Ok(v) => self.N = v,
+                Err(_) => {
+                    errors.push(spec(pos, SpecError::Type(k.to_owned())));
+                    return false;
                 }
-                if !tm.Q.contains(p) {
-                    return Err((
-                        pos,
-                        ParseError::Spec(SpecError::TInvalidState(p.to_owned())),
-                    ));
+            },
+            ("B", Value::Str(v)) => match symbol(pos, &v) {
+                Ok(ch) if valid_symbol_char(ch) => self.B = ch,
+                Ok(ch) => {
+                    errors.push(spec(pos, SpecError::GChar(ch)));
+                    return false;
                 }
-
-                let direction: Vec<Direction>;
-                if let Ok(d) = D.chars().map(|d| d.try_into()).collect() {
-                    direction = d;
-                } else {
-                    return Err((pos, ParseError::Spec(SpecError::TInvalidDirection)));
+                Err(e) => {
+                    errors.push(e);
+                    return false;
                 }
-
-                let mut X_vec = Vec::new();
-                let mut Y_vec = Vec::new();
-                let X = X_str.chars();
-                let Y = Y_str.chars();
-
-                for (X, Y) in X.zip(Y) {
-                    if X != '*' && Y == '*' {
-                        return Err((
-                            pos,
-                            ParseError::Spec(SpecError::TGlob(X_str.to_owned(), Y_str.to_owned())),
-                        ));
+            },
+            ("q0", Value::Str(v)) => self.q0 = v,
+            ("dialect", Value::Str(v)) => match v.parse() {
+                Ok(d) => self.dialect = d,
+                Err(_) => {
+                    errors.push(spec(pos, SpecError::Dialect(v)));
+                    return false;
+                }
+            },
+            ("Q" | "F", Value::Set(v)) => {
+                for (pos, state) in v {
+                    if let Some((i, ch)) =
+                        state.char_indices().find(|(_, ch)| !valid_state_char(*ch))
+                    {
+                        let pos = Position {
+                            col: pos.col + i,
+                            len: ch.len_utf8(),
+                            ..pos
+                        };
+                        errors.push(spec(&pos, SpecError::QChar(ch)));
+                    }
+                    match k {
+                        "Q" => self.Q.insert(state),
+                        _ => self.F.insert(state),
+                    };
+                }
+            }
+            ("S", Value::Set(v)) => {
+                for (pos, s) in v {
+                    match symbol(&pos, &s) {
+                        Ok(ch) if valid_symbol_char(ch) && ch != '_' => {
+                            self.S.insert(ch);
+                        }
+                        Ok(ch) => errors.push(spec(&pos, SpecError::SChar(ch))),
+                        Err(e) => errors.push(e),
                     }
-                    for ch in [X, Y] {
-                        if !tm.G.contains(&ch) && ch != '*' {
-                            return Err((pos, ParseError::Spec(SpecError::TInvalidSymbol(ch))));
+                }
+            }
+            ("G", Value::Set(v)) => {
+                for (pos, s) in v {
+                    match symbol(&pos, &s) {
+                        Ok(ch) if valid_symbol_char(ch) => {
+                            self.G.insert(ch);
+                        }
+                        Ok(ch) => errors.push(spec(&pos, SpecError::GChar(ch))),
+                        Err(e) => errors.push(e),
+                    }
+                }
+            }
+            ("V", Value::Set(v)) => {
+                for (pos, var) in v {
+                    match symbol(&pos, &var) {
+                        Ok(ch)
+                            if valid_symbol_char(ch)
+                                && !self.G.contains(&ch)
+                                && !['l', 'r'].contains(&ch) =>
+                        {
+                            self.V.insert(ch);
                         }
+                        Ok(ch) => errors.push(spec(&pos, SpecError::VChar(ch))),
+                        Err(e) => errors.push(e),
                     }
-                    X_vec.push(X);
-                    Y_vec.push(Y);
                 }
+            }
+            _ => {
+                errors.push(spec(pos, SpecError::Type(k.to_owned())));
+                return false;
+            }
+        }
+        true
+    }
 
-                tm.delta
-                    .push(((q.to_owned(), X_vec), (Y_vec, direction, p.to_owned())));
+    /// Check a rule and add it, expanding its variables.
+    fn rule(&mut self, pos: &Position, t: Vec<String>) -> Result<(), (Position, ParseError)> {
+        let [q, X_str, Y_str, D, p] = &t[..] else {
+            return Err(spec(pos, SpecError::TLen(t)));
+        };
+        let X_cells = cells(X_str);
+        for (k, n) in [
+            (1, X_cells.len()),
+            (2, Y_str.chars().count()),
+            (3, D.chars().count()),
+        ] {
+            if n != self.N {
+                return Err(spec(&pos.field(k), SpecError::TtsLen(self.N)));
+            }
+        }
+        for (k, state) in [(0, q), (4, p)] {
+            if !self.Q.contains(state) {
+                return Err(spec(
+                    &pos.field(k),
+                    SpecError::TInvalidState(state.to_owned()),
+                ));
+            }
+        }
+
+        // `*`, a class or a variable may match several symbols, so they may pair
+        // with a `*` write
+        for (X, (at, Y)) in X_cells.iter().zip(Y_str.char_indices()) {
+            let mut X_chars = X.chars();
+            if let (Some(X), None) = (X_chars.next(), X_chars.next()) {
+                if X != '*' && !self.V.contains(&X) && Y == '*' && !self.dialect.glob_keep {
+                    let write = pos.field(2);
+                    let pos = Position {
+                        col: write.col + at,
+                        len: 1,
+                        ..write
+                    };
+                    return Err(spec(&pos, SpecError::TGlob(X.to_string())));
+                }
+            }
+        }
+
+        if let Some(d) = D
+            .chars()
+            .find(|d| Direction::try_from(*d).is_err() && !self.V.contains(d))
+        {
+            return Err(spec(&pos.symbol(&[3], d), SpecError::TInvalidDirection(d)));
+        }
+
+        let rules = match self.expand(&X_cells, Y_str, D) {
+            Ok(rules) => rules,
+            Err(SpecError::TUnboundVar(v)) => {
+                return Err(spec(&pos.symbol(&[2, 3], v), SpecError::TUnboundVar(v)))
+            }
+            Err(SpecError::TVarEmpty(v)) => {
+                return Err(spec(&pos.symbol(&[3, 1], v), SpecError::TVarEmpty(v)))
+            }
+            Err(e) => return Err(spec(pos, e)),
+        };
+        for (X_cells, Y_str, D) in &rules {
+            let direction: Vec<Direction>;
+            if let Ok(d) = D.chars().map(|d| d.try_into()).collect() {
+                direction = d;
             } else {
-                return Err((pos, ParseError::Spec(SpecError::TLen(t))));
+                return Err(spec(&pos.field(3), SpecError::TInvalidDirection('?')));
+            }
+
+            let mut X_vec = Vec::new();
+            for X in X_cells {
+                let X = match Pattern::parse(X) {
+                    Ok(X) => X,
+                    Err(e) => return Err(spec(&pos.field(1), SpecError::TInvalidClass(e))),
+                };
+                for ch in X.symbols() {
+                    if !self.G.contains(&ch) {
+                        return Err(spec(&pos.symbol(&[1], ch), SpecError::TInvalidSymbol(ch)));
+                    }
+                }
+                X_vec.push(X);
+            }
+            let mut Y_vec = Vec::new();
+            for Y in Y_str.chars() {
+                if !self.G.contains(&Y) && Y != '*' {
+                    return Err(spec(&pos.symbol(&[2], Y), SpecError::TInvalidSymbol(Y)));
+                }
+                Y_vec.push(Y);
+            }
+
+            self.delta
+                .push(((q.to_owned(), X_vec), (Y_vec, direction, p.to_owned())));
+            self.source.rules.push(pos.clone());
+        }
+        Ok(())
+    }
+
+    /// Instantiate the variables of a rule with every symbol they may capture,
+    /// i.e. what `*` matches. A variable used as a direction only captures `l`, `r` and `*`.
+    fn expand(
+        &self,
+        X: &[String],
+        Y: &str,
+        D: &str,
+    ) -> Result<Vec<(Vec<String>, String, String)>, SpecError> {
+        let var = |cell: &String| {
+            let mut chars = cell.chars();
+            match (chars.next(), chars.next()) {
+                (Some(ch), None) if self.V.contains(&ch) => Some(ch),
+                _ => None,
+            }
+        };
+        let mut vars: Vec<char> = Vec::new();
+        for ch in X.iter().filter_map(var) {
+            if !vars.contains(&ch) {
+                vars.push(ch);
             }
         }
+        for ch in Y.chars().chain(D.chars()) {
+            if self.V.contains(&ch) && !vars.contains(&ch) {
+                return Err(SpecError::TUnboundVar(ch));
+            }
+        }
+
+        let mut range: Vec<TapeSymbol> = self
+            .G
+            .iter()
+            .copied()
+            .filter(|g| self.matches(&Pattern::Any, *g))
+            .collect();
+        range.sort();
 
-        Ok(tm)
+        let mut rules = vec![(X.to_vec(), Y.to_owned(), D.to_owned())];
+        for v in vars {
+            rules = rules
+                .iter()
+                .flat_map(|(X, Y, D)| {
+                    range
+                        .iter()
+                        .filter(|g| !D.contains(v) || Direction::try_from(**g).is_ok())
+                        .map(|g| {
+                            let g = g.to_string();
+                            let X = X
+                                .iter()
+                                .map(|cell| match var(cell) {
+                                    Some(u) if u == v => g.clone(),
+                                    _ => cell.clone(),
+                                })
+                                .collect();
+                            (X, Y.replace(v, &g), D.replace(v, &g))
+                        })
+                        .collect::<Vec<_>>()
+                })
+                .collect();
+            if rules.is_empty() {
+                return Err(SpecError::TVarEmpty(v));
+            }
+        }
+        Ok(rules)
     }
 }
 
@@ -398,13 +719,22 @@ pub struct ArchState {
     /// (index (on abstarct tape), offset (on VecDeque))
     heads: Vec<(isize, usize)>,
     halt: bool,
+    /// index into `delta` of the rule the last step applied
+    rule: Option<usize>,
+    /// what the last step wrote on each tape, empty before the first step
+    written: Vec<TapeSymbol>,
+    /// undo log, `None` unless recording
+    history: Option<Vec<Undo>>,
 }
 
-#[derive(Debug, Clone)]
-pub(crate) enum Exception {
-    InvalidInput { input: String, offset: usize },
-    Reject(String),
-    Accept(String),
+/// What a step overwrote: the previous state, rule and written symbols and, per tape, the index of the first cell kept,
+/// the number of cells kept, the head and the symbol under it.
+#[derive(Clone, Debug)]
+struct Undo {
+    state: State,
+    rule: Option<usize>,
+    written: Vec<TapeSymbol>,
+    tapes: Vec<(isize, usize, (isize, usize), TapeSymbol)>,
 }
 
 impl ArchState {
@@ -423,6 +753,9 @@ impl ArchState {
             tapes,
             heads: vec![(0, 0); N],
             halt: false,
+            rule: None,
+            written: Vec::new(),
+            history: None,
         }
     }
 
@@ -452,7 +785,7 @@ impl ArchState {
 }
 
 impl super::ArchState for ArchState {
-    fn input(&mut self, s: &str) -> Result<(), super::Exception> {
+    fn input(&mut self, s: &str) -> Result<(), Error> {
         match self.tm.input_valid(s) {
             Ok(()) => {
                 self.tapes[0] = VecDeque::from_iter(s.to_owned().chars());
@@ -460,42 +793,68 @@ impl super::ArchState for ArchState {
                     self.tapes[0] = VecDeque::from([self.tm.B()])
                 }
             }
-            Err(offset) => {
-                return Err(super::Exception::Tm(Exception::InvalidInput {
-                    input: s.to_owned(),
-                    offset,
-                }))
+            Err(col) => {
+                return Err(Error::InvalidInput {
+                    symbol: s.chars().nth(col).unwrap(),
+                    col,
+                })
             }
         }
         Ok(())
     }
 
-    fn step(&mut self) -> Result<(), super::Exception> {
+    fn state(&self) -> &str {
+        &self.state
+    }
+
+    fn steps(&self) -> usize {
+        self.step
+    }
+
+    fn heads(&self) -> Vec<char> {
+        self.tapes
+            .iter()
+            .zip(self.heads.iter())
+            .map(|(t, (_, off))| t[*off])
+            .collect()
+    }
+
+    fn step(&mut self) -> StepOutcome {
         if self.tm.F().contains(&self.state) {
             self.halt = true;
-            return Err(super::Exception::Tm(Exception::Accept(
-                self.result().unwrap(),
-            )));
+            return StepOutcome::Accept(self.result());
         }
-        match self.tm.get(
-            &self.state,
-            &self
-                .tapes
-                .iter()
-                .zip(self.heads.iter())
-                .map(|(t, (_, off))| t[*off])
-                .collect::<Vec<char>>(),
-        ) {
-            Some((nts, dirs, new_state)) => {
+        match self.tm.get(&self.state, &super::ArchState::heads(self)) {
+            Some((k, (nts, dirs, new_state))) => {
+                if let Some(history) = &mut self.history {
+                    history.push(Undo {
+                        state: self.state.clone(),
+                        rule: self.rule,
+                        written: self.written.clone(),
+                        tapes: self
+                            .tapes
+                            .iter()
+                            .zip(self.heads.iter())
+                            .map(|(tape, head)| {
+                                (head.0 - head.1 as isize, tape.len(), *head, tape[head.1])
+                            })
+                            .collect(),
+                    });
+                }
                 self.state = new_state;
-                for i in 0..self.tm.N() {
-                    let tape = &mut self.tapes[i];
-                    let head = &mut self.heads[i];
-                    tape[head.1] = (*nts)[i];
-
-                    let B = self.tm.B();
+                self.rule = Some(k);
+                self.written = nts.clone();
+                let B = self.tm.B();
+                for (((tape, head), nt), dir) in self
+                    .tapes
+                    .iter_mut()
+                    .zip(self.heads.iter_mut())
+                    .zip(nts)
+                    .zip(dirs)
+                {
+                    tape[head.1] = nt;
 
-                    match dirs[i] {
+                    match dir {
                         Direction::Stay => (),
                         Direction::Left => {
                             if head.1 == 0 {
@@ -526,26 +885,84 @@ impl super::ArchState for ArchState {
                     }
                 }
                 self.step += 1;
-                Ok(())
+                StepOutcome::Continue
             }
             None => {
                 self.halt = true;
-                Err(super::Exception::Tm(Exception::Reject(
-                    self.result().unwrap(),
-                )))
+                StepOutcome::Reject(self.result())
+            }
+        }
+    }
+
+    fn rule(&self) -> Option<&Position> {
+        self.rule.map(|k| &self.tm.source().rules[k])
+    }
+
+    fn view(&self) -> super::View {
+        super::View::Tm {
+            blank: self.tm.B(),
+            tapes: self
+                .tapes
+                .iter()
+                .zip(self.heads.iter())
+                .enumerate()
+                .map(|(i, (tape, head))| super::Tape {
+                    first: head.0 - head.1 as isize,
+                    cells: tape.iter().copied().collect(),
+                    head: head.1,
+                    written: self.written.get(i).copied(),
+                })
+                .collect(),
+        }
+    }
+
+    fn record(&mut self) {
+        self.history.get_or_insert_with(Vec::new);
+    }
+
+    fn back(&mut self) -> bool {
+        let Some(undo) = self.history.as_mut().and_then(|h| h.pop()) else {
+            return false;
+        };
+        let B = self.tm.B();
+        for ((tape, head), (first, len, old_head, old)) in self
+            .tapes
+            .iter_mut()
+            .zip(self.heads.iter_mut())
+            .zip(undo.tapes)
+        {
+            // only blanks are ever added to or trimmed from either end
+            let mut front = head.0 - head.1 as isize;
+            while front > first {
+                tape.push_front(B);
+                front -= 1;
             }
+            while front < first {
+                tape.pop_front();
+                front += 1;
+            }
+            tape.resize(len, B);
+            *head = old_head;
+            tape[head.1] = old;
         }
+        self.state = undo.state;
+        self.rule = undo.rule;
+        self.written = undo.written;
+        self.step -= 1;
+        self.halt = false;
+        true
     }
 }
 
 impl std::fmt::Display for ArchState {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         writeln!(f, "Step   : {}", self.step)?;
-        for i in 0..self.tm.N() {
-            let head = &self.heads[i];
-            let tape = &self.tapes[i];
-            let indices: Vec<_> = (0..tape.len())
-                .map(|pos| (pos as isize + head.0 - head.1 as isize).abs())
+        let super::View::Tm { tapes, .. } = super::ArchState::view(self) else {
+            unreachable!()
+        };
+        for (i, tape) in tapes.iter().enumerate() {
+            let indices: Vec<_> = (0..tape.cells.len())
+                .map(|pos| (tape.first + pos as isize).abs())
                 .collect();
             let widths: Vec<_> = indices
                 .iter()
@@ -557,18 +974,217 @@ impl std::fmt::Display for ArchState {
             }
             writeln!(f)?;
             write!(f, "Tape{:<3}: ", i)?;
-            for (pos, width) in (0..tape.len()).zip(widths.iter()) {
-                write!(f, "{:<width$}", tape[pos], width = width)?;
+            for (cell, width) in tape.cells.iter().zip(widths.iter()) {
+                write!(f, "{:<width$}", cell, width = width)?;
             }
             writeln!(f)?;
             writeln!(
                 f,
                 "Head{:<3}: {}",
                 i,
-                " ".repeat(widths[..head.1].iter().sum()) + "^"
+                " ".repeat(widths[..tape.head].iter().sum()) + "^"
             )?;
         }
         writeln!(f, "State  : {}", self.state)?;
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::automata::ArchState as _;
+
+    /// Copies the input to tape 1, then walks both heads back to the start.
+    const COPY: &str = "\
+#Q = {cp,back,done}
+#S = {a,b}
+#G = {a,b,_}
+#q0 = cp
+#B = _
+#F = {done}
+#N = 2
+
+cp a_ aa rr cp
+cp b_ bb rr cp
+cp __ __ ll back
+back ** ** ll back
+back __ __ rr done
+";
+
+    #[test]
+    fn back_retraces_a_run() {
+        let mut arch_state = ArchState::new(COPY.parse().unwrap());
+        arch_state.input("abba").unwrap();
+        arch_state.record();
+        let mut seen = vec![arch_state.to_string()];
+        let outcome = loop {
+            let outcome = arch_state.step();
+            if outcome.halted() {
+                break outcome;
+            }
+            seen.push(arch_state.to_string());
+        };
+        assert_eq!(outcome.output(), Some("abba"));
+        while let Some(snapshot) = seen.pop() {
+            assert_eq!(arch_state.to_string(), snapshot);
+            assert_eq!(arch_state.back(), !seen.is_empty());
+        }
+    }
+
+    #[test]
+    fn back_needs_a_record() {
+        let mut arch_state = ArchState::new(COPY.parse().unwrap());
+        arch_state.input("ab").unwrap();
+        arch_state.step();
+        assert!(!arch_state.back());
+    }
+
+    #[test]
+    fn errors_come_in_source_order() {
+        let program = "\
+#N = 1
+#Q = {q0,q-1,acc}
+#S = {a,bb}
+#G = {a,_}
+#q0 = q0
+#B = _
+#F = {acc,zz}
+#X = 3
+q0 a a r acc
+q0 b _ r
+";
+        let errors = program.parse::<TuringMachine>().unwrap_err();
+        let found: Vec<(usize, &str)> = errors.iter().map(|(pos, e)| (pos.row, e.code())).collect();
+        assert_eq!(
+            found,
+            [
+                (1, "E0204"),
+                (2, "E0207"),
+                (6, "E0209"),
+                (7, "E0202"),
+                (9, "E0104"),
+            ]
+        );
+    }
+
+    /// Accepts on a blank only if `*` matches it, and writes `*` over a read `a`.
+    const GLOB: &str = "\
+#Q = {q,acc}
+#S = {a}
+#G = {a,_}
+#q0 = q
+#B = _
+#F = {acc}
+#N = 1
+
+q a * r acc
+q * _ * acc
+";
+
+    fn run_on(tm: TuringMachine, input: &str) -> StepOutcome {
+        let mut arch_state = ArchState::new(tm);
+        arch_state.input(input).unwrap();
+        crate::automata::run(&mut arch_state, Some(100), |_| ())
+    }
+
+    #[test]
+    fn dialect_2024_rejects_a_kept_write() {
+        let errors = GLOB.parse::<TuringMachine>().unwrap_err();
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].0.line(), 9);
+    }
+
+    #[test]
+    fn dialect_glob_keep_keeps_the_read_symbol() {
+        let dialect = "glob_keep".parse().unwrap();
+        let tm = TuringMachine::parse_with(GLOB, Some(dialect)).unwrap();
+        assert_eq!(run_on(tm.clone(), "a").output(), Some("a"));
+        // `*` still does not match the blank
+        assert!(!run_on(tm, "").accepted());
+    }
+
+    #[test]
+    fn dialect_declared_in_the_file() {
+        let program = GLOB.replace("#N = 1", "#N = 1\n#dialect = relaxed");
+        let tm: TuringMachine = program.parse().unwrap();
+        assert!(run_on(tm, "").accepted());
+    }
+
+    #[test]
+    fn dialect_argument_overrides_the_declaration() {
+        let program = GLOB.replace("#N = 1", "#N = 1\n#dialect = glob_keep");
+        let relaxed = "relaxed".parse().unwrap();
+        assert!(run_on(
+            TuringMachine::parse_with(&program, Some(relaxed)).unwrap(),
+            ""
+        )
+        .accepted());
+        let strict = "2024".parse().unwrap();
+        assert!(TuringMachine::parse_with(&program, Some(strict)).is_err());
+    }
+
+    #[test]
+    fn dialect_names_round_trip() {
+        for name in ["2024", "glob_blank", "glob_keep", "relaxed"] {
+            assert_eq!(name.parse::<Dialect>().unwrap().to_string(), name);
+        }
+        assert!("2021".parse::<Dialect>().is_err());
+    }
+
+    fn with_vars(
+        G: &str,
+        N: usize,
+        rule: &str,
+    ) -> Result<TuringMachine, Vec<(Position, ParseError)>> {
+        format!(
+            "#Q = {{q,acc}}\n#S = {{a,b}}\n#G = {{{}}}\n#q0 = q\n#B = _\n#F = {{acc}}\n#N = {}\n#V = {{x}}\n{}\n",
+            G, N, rule
+        )
+        .parse()
+    }
+
+    fn codes(errors: &[(Position, ParseError)]) -> Vec<(usize, usize, &'static str)> {
+        errors
+            .iter()
+            .map(|(pos, e)| (pos.line(), pos.col + 1, e.code()))
+            .collect()
+    }
+
+    #[test]
+    fn var_expands_over_what_star_matches() {
+        let tm = with_vars("a,b,_", 1, "q x x r q").unwrap();
+        let rules: Vec<(Vec<char>, Vec<char>)> = tm
+            .delta()
+            .iter()
+            .map(|((_, X), (Y, _, _))| (X[0].symbols(), Y.clone()))
+            .collect();
+        assert_eq!(rules, [(vec!['a'], vec!['a']), (vec!['b'], vec!['b'])]);
+        // both point at the line they were written on
+        assert!(tm.source().rules.iter().all(|pos| pos.line() == 9));
+    }
+
+    #[test]
+    fn var_on_several_tapes_reads_the_same_symbol() {
+        let tm = with_vars("a,b,_", 2, "q xx __ ** acc").unwrap();
+        assert_eq!(tm.delta().len(), 2);
+        let mut arch_state = ArchState::new(tm);
+        arch_state.input("a").unwrap();
+        // tape 1 holds a blank, not the `a` under tape 0
+        assert!(!crate::automata::run(&mut arch_state, Some(10), |_| ()).accepted());
+    }
+
+    #[test]
+    fn var_as_a_direction_captures_only_directions() {
+        let tm = with_vars("a,b,l,r,_", 1, "q x x x q").unwrap();
+        assert_eq!(tm.delta().len(), 2);
+    }
+
+    #[test]
+    fn var_errors() {
+        let unbound = with_vars("a,b,_", 1, "q a x r q").unwrap_err();
+        assert_eq!(codes(&unbound), [(9, 5, "E0308")]);
+        let empty = with_vars("a,b,_", 1, "q x x x q").unwrap_err();
+        assert_eq!(codes(&empty), [(9, 7, "E0310")]);
+    }
+}
@@ -0,0 +1,148 @@
+use crate::automata::{self, ArchState, StepOutcome};
+use crate::parse::{self, Position};
+
+/// What a run yields on an input with undeclared symbols.
+pub const ILLEGAL: &str = "illegal input";
+
+/// An input and the result it should give: `true`/`false` for a DFA or PDA, the content of
+/// tape 0 for a TM, or `illegal input`.
+#[derive(Clone, Debug)]
+pub struct Case {
+    pub input: String,
+    pub expected: String,
+}
+
+/// Read a cases file, one `input => expected` per line, `;` starts a comment.
+pub fn parse(s: &str) -> Result<Vec<Case>, Vec<(Position, parse::ParseError)>> {
+    let mut cases = Vec::new();
+    let mut errors = Vec::new();
+    for (row, line) in s.lines().enumerate() {
+        let code = line.split_once(';').map_or(line, |(code, _)| code);
+        if code.trim().is_empty() {
+            continue;
+        }
+        let pos = Position {
+            inst: line.to_owned(),
+            row,
+            col: code.len() - code.trim_start().len(),
+            len: code.trim().len(),
+        };
+        match parse::parse_case(code) {
+            Some((input, expected)) => cases.push(Case { input, expected }),
+            None => errors.push((pos, parse::ParseError::Case)),
+        }
+    }
+    match errors.is_empty() {
+        true => Ok(cases),
+        false => Err(errors),
+    }
+}
+
+/// What a machine gave on an input.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Outcome {
+    Illegal,
+    /// still running after this many steps
+    Timeout(usize),
+    Verdict(bool),
+    /// what a TM left on tape 0
+    Output(String),
+}
+
+impl std::fmt::Display for Outcome {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Outcome::Illegal => write!(f, "{}", ILLEGAL),
+            Outcome::Timeout(n) => write!(f, "no verdict after {} steps", n),
+            Outcome::Verdict(accepted) => write!(f, "{}", accepted),
+            Outcome::Output(s) => write!(f, "{}", s),
+        }
+    }
+}
+
+impl Case {
+    pub fn passes(&self, outcome: &Outcome) -> bool {
+        outcome.to_string() == self.expected
+    }
+}
+
+/// Run a fresh machine on `input` for at most `max_steps` steps.
+pub fn run(arch_state: &mut dyn ArchState, input: &str, max_steps: usize) -> Outcome {
+    if arch_state.input(input).is_err() {
+        return Outcome::Illegal;
+    }
+    match automata::run(arch_state, Some(max_steps), |_| ()) {
+        StepOutcome::Continue => Outcome::Timeout(max_steps),
+        outcome => match outcome.output() {
+            Some(s) => Outcome::Output(s.to_owned()),
+            None => Outcome::Verdict(outcome.accepted()),
+        },
+    }
+}
+
+/// Every word over `alphabet` of at most `max_len` symbols, shorter words first, words of
+/// the same length in alphabet order.
+pub fn shortlex(alphabet: &[char], max_len: usize) -> impl Iterator<Item = String> + '_ {
+    (0..=max_len).flat_map(move |len| {
+        let mut next = (len == 0 || !alphabet.is_empty()).then(|| vec![0; len]);
+        std::iter::from_fn(move || {
+            let digits = next.take()?;
+            let word = digits.iter().map(|&k| alphabet[k]).collect();
+            // count up, the last symbol changing fastest
+            let mut digits = digits;
+            for k in (0..len).rev() {
+                digits[k] += 1;
+                if digits[k] < alphabet.len() {
+                    next = Some(digits);
+                    break;
+                }
+                digits[k] = 0;
+            }
+            Some(word)
+        })
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn shortlex_order() {
+        let words: Vec<String> = shortlex(&['a', 'b'], 2).collect();
+        assert_eq!(words, ["", "a", "b", "aa", "ab", "ba", "bb"]);
+    }
+
+    #[test]
+    fn shortlex_counts() {
+        assert_eq!(shortlex(&['a', 'b', 'c'], 3).count(), 1 + 3 + 9 + 27);
+        assert_eq!(shortlex(&['a'], 0).collect::<Vec<_>>(), [""]);
+        assert_eq!(shortlex(&[], 3).collect::<Vec<_>>(), [""]);
+    }
+
+    #[test]
+    fn cases_file() {
+        let cases = parse("; comment\nab => ab\n\n => illegal input ; empty\n").unwrap();
+        let cases: Vec<(&str, &str)> = cases
+            .iter()
+            .map(|case| (case.input.as_str(), case.expected.as_str()))
+            .collect();
+        assert_eq!(cases, [("ab", "ab"), ("", "illegal input")]);
+        let errors = parse("a => b\nno arrow\n").unwrap_err();
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].0.line(), 2);
+    }
+
+    #[test]
+    fn passes() {
+        let case = |expected: &str| Case {
+            input: String::new(),
+            expected: expected.to_owned(),
+        };
+        assert!(case("true").passes(&Outcome::Verdict(true)));
+        assert!(!case("true").passes(&Outcome::Verdict(false)));
+        assert!(case("abc").passes(&Outcome::Output("abc".to_owned())));
+        assert!(case(ILLEGAL).passes(&Outcome::Illegal));
+        assert!(!case("abc").passes(&Outcome::Timeout(10)));
+    }
+}
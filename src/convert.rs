@@ -0,0 +1,416 @@
+use serde::{Deserialize, Serialize};
+
+use crate::parse::{self, Diagnostic, Position, Value};
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct DfaRule {
+    pub from: String,
+    pub read: String,
+    pub to: String,
+}
+
+/// A TM rule, each field in transition syntax with one cell per tape.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct TmRule {
+    pub from: String,
+    pub read: String,
+    pub write: String,
+    #[serde(rename = "move")]
+    pub dir: String,
+    pub to: String,
+}
+
+/// A PDA rule, `input` is `_` for an epsilon move and `push` is `_` to push nothing.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct PdaRule {
+    pub from: String,
+    pub input: String,
+    pub pop: String,
+    pub to: String,
+    pub push: String,
+}
+
+/// A `#TEST input => expected` line.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct TestCase {
+    pub input: String,
+    pub expected: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Dfa {
+    pub Q: Vec<String>,
+    pub S: Vec<String>,
+    pub q0: String,
+    pub F: Vec<String>,
+    pub delta: Vec<DfaRule>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tests: Vec<TestCase>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Tm {
+    pub N: usize,
+    pub Q: Vec<String>,
+    pub S: Vec<String>,
+    pub G: Vec<String>,
+    pub q0: String,
+    pub B: String,
+    pub F: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub dialect: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub V: Option<Vec<String>>,
+    /// in source order, the first matching rule wins
+    pub delta: Vec<TmRule>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tests: Vec<TestCase>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Pda {
+    pub Q: Vec<String>,
+    pub S: Vec<String>,
+    pub G: Vec<String>,
+    pub q0: String,
+    pub z0: String,
+    pub F: Vec<String>,
+    /// in source order, the last matching rule wins
+    pub delta: Vec<PdaRule>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tests: Vec<TestCase>,
+}
+
+/// A machine description as plain data, declarations keep the names of the native syntax.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(tag = "kind", rename_all = "lowercase")]
+pub enum Description {
+    Dfa(Dfa),
+    Tm(Tm),
+    Pda(Pda),
+}
+
+#[derive(Debug)]
+pub enum Error {
+    Syntax(Position, parse::ParseError),
+    Json(String),
+    Yaml(String),
+    /// a declaration the kind of machine needs is missing or has the wrong type
+    Decl(String),
+}
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Error::Syntax(pos, e) => write!(f, "{} ({})\n{}", e, e.code(), pos),
+            Error::Json(e) => write!(f, "invalid JSON description: {}", e),
+            Error::Yaml(e) => write!(f, "invalid YAML description: {}", e),
+            Error::Decl(k) => write!(f, "#{} is missing or has the wrong type", k),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+/// The first syntax error, the machine parser reports them all.
+fn syntax(mut errors: Vec<(Position, parse::ParseError)>) -> Error {
+    let (pos, e) = errors.remove(0);
+    Error::Syntax(pos, e)
+}
+
+fn tests(cases: Vec<crate::cases::Case>) -> Vec<TestCase> {
+    cases
+        .into_iter()
+        .map(|case| TestCase {
+            input: case.input,
+            expected: case.expected,
+        })
+        .collect()
+}
+
+struct Decls(parse::KVStore);
+
+impl Decls {
+    fn set(&mut self, k: &str) -> Result<Vec<String>, Error> {
+        match self.0.remove(k) {
+            Some((_, Value::Set(items))) => Ok(items.into_iter().map(|(_, item)| item).collect()),
+            _ => Err(Error::Decl(k.to_owned())),
+        }
+    }
+
+    fn str(&mut self, k: &str) -> Result<String, Error> {
+        match self.0.remove(k) {
+            Some((_, Value::Str(s))) => Ok(s),
+            _ => Err(Error::Decl(k.to_owned())),
+        }
+    }
+}
+
+impl Description {
+    /// Read a native description, which should already have been checked by the machine parser.
+    pub fn dfa(s: &str) -> Result<Self, Error> {
+        let c = parse::parse(s, 3).map_err(syntax)?;
+        let mut decls = Decls(c.store);
+        Ok(Description::Dfa(Dfa {
+            Q: decls.set("Q")?,
+            S: decls.set("S")?,
+            q0: decls.str("q0")?,
+            F: decls.set("F")?,
+            delta: c
+                .trans
+                .into_iter()
+                .map(|(_, t)| {
+                    let [from, read, to] = <[String; 3]>::try_from(t).unwrap();
+                    DfaRule { from, read, to }
+                })
+                .collect(),
+            tests: tests(c.tests),
+        }))
+    }
+
+    /// Read a native description, which should already have been checked by the machine parser.
+    pub fn tm(s: &str) -> Result<Self, Error> {
+        let c = parse::parse(s, 5).map_err(syntax)?;
+        let mut decls = Decls(c.store);
+        let N = decls.str("N")?;
+        Ok(Description::Tm(Tm {
+            N: N.parse().map_err(|_| Error::Decl("N".to_owned()))?,
+            Q: decls.set("Q")?,
+            S: decls.set("S")?,
+            G: decls.set("G")?,
+            q0: decls.str("q0")?,
+            B: decls.str("B")?,
+            F: decls.set("F")?,
+            dialect: decls.str("dialect").ok(),
+            V: decls.set("V").ok(),
+            delta: c
+                .trans
+                .into_iter()
+                .map(|(_, t)| {
+                    let [from, read, write, dir, to] = <[String; 5]>::try_from(t).unwrap();
+                    TmRule {
+                        from,
+                        read,
+                        write,
+                        dir,
+                        to,
+                    }
+                })
+                .collect(),
+            tests: tests(c.tests),
+        }))
+    }
+
+    /// Read a native description, which should already have been checked by the machine parser.
+    pub fn pda(s: &str) -> Result<Self, Error> {
+        let c = parse::parse(s, 5).map_err(syntax)?;
+        let mut decls = Decls(c.store);
+        Ok(Description::Pda(Pda {
+            Q: decls.set("Q")?,
+            S: decls.set("S")?,
+            G: decls.set("G")?,
+            q0: decls.str("q0")?,
+            z0: decls.str("z0")?,
+            F: decls.set("F")?,
+            delta: c
+                .trans
+                .into_iter()
+                .map(|(_, t)| {
+                    let [from, input, pop, to, push] = <[String; 5]>::try_from(t).unwrap();
+                    PdaRule {
+                        from,
+                        input,
+                        pop,
+                        to,
+                        push,
+                    }
+                })
+                .collect(),
+            tests: tests(c.tests),
+        }))
+    }
+
+    pub fn from_json(s: &str) -> Result<Self, Error> {
+        serde_json::from_str(s).map_err(|e| Error::Json(e.to_string()))
+    }
+
+    pub fn from_yaml(s: &str) -> Result<Self, Error> {
+        serde_yaml::from_str(s).map_err(|e| Error::Yaml(e.to_string()))
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).unwrap() + "\n"
+    }
+
+    pub fn to_yaml(&self) -> String {
+        serde_yaml::to_string(self).unwrap()
+    }
+
+    /// The native `.dfa` / `.tm` / `.pda` syntax, rules in the same order.
+    pub fn to_native(&self) -> String {
+        let set = |k: &str, items: &[String]| format!("#{} = {{{}}}\n", k, items.join(","));
+        let scalar = |k: &str, s: &str| format!("#{} = {}\n", k, s);
+        let mut out = String::new();
+        let (rules, tests): (Vec<Vec<&str>>, _) = match self {
+            Description::Dfa(dfa) => {
+                out += &set("Q", &dfa.Q);
+                out += &set("S", &dfa.S);
+                out += &scalar("q0", &dfa.q0);
+                out += &set("F", &dfa.F);
+                let rules = dfa.delta.iter();
+                (
+                    rules
+                        .map(|r| vec![r.from.as_str(), &r.read, &r.to])
+                        .collect(),
+                    &dfa.tests,
+                )
+            }
+            Description::Tm(tm) => {
+                out += &scalar("N", &tm.N.to_string());
+                out += &set("Q", &tm.Q);
+                out += &set("S", &tm.S);
+                out += &set("G", &tm.G);
+                out += &scalar("q0", &tm.q0);
+                out += &scalar("B", &tm.B);
+                out += &set("F", &tm.F);
+                if let Some(dialect) = &tm.dialect {
+                    out += &scalar("dialect", dialect);
+                }
+                if let Some(V) = &tm.V {
+                    out += &set("V", V);
+                }
+                let rules = tm.delta.iter();
+                (
+                    rules
+                        .map(|r| vec![r.from.as_str(), &r.read, &r.write, &r.dir, &r.to])
+                        .collect(),
+                    &tm.tests,
+                )
+            }
+            Description::Pda(pda) => {
+                out += &set("Q", &pda.Q);
+                out += &set("S", &pda.S);
+                out += &set("G", &pda.G);
+                out += &scalar("q0", &pda.q0);
+                out += &scalar("z0", &pda.z0);
+                out += &set("F", &pda.F);
+                let rules = pda.delta.iter();
+                (
+                    rules
+                        .map(|r| vec![r.from.as_str(), &r.input, &r.pop, &r.to, &r.push])
+                        .collect(),
+                    &pda.tests,
+                )
+            }
+        };
+        out += "\n";
+        for rule in rules {
+            out += &rule.join(" ");
+            out += "\n";
+        }
+        if !tests.is_empty() {
+            out += "\n";
+        }
+        for case in tests {
+            match case.input.as_str() {
+                "" => out += &format!("#TEST => {}\n", case.expected),
+                input => out += &format!("#TEST {} => {}\n", input, case.expected),
+            }
+        }
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TM: &str = "\
+#Q = {cp,done}
+#S = {a,b}
+#G = {a,b,_}
+#q0 = cp
+#B = _
+#F = {done}
+#N = 2
+#dialect = glob_keep
+#V = {x}
+#TEST ab => ab
+
+cp x_ xx rr cp
+cp __ __ ** done
+";
+
+    const PDA: &str = "\
+#Q = {q,acc}
+#S = {a}
+#G = {z,1}
+#q0 = q
+#z0 = z
+#F = {acc}
+#TEST => false
+
+q a z q 1z
+q _ 1 acc _
+";
+
+    const DFA: &str = "\
+#Q = {q}
+#S = {a}
+#q0 = q
+#F = {q}
+
+q a q
+";
+
+    /// Native text through JSON and YAML and back comes out the same.
+    fn round_trip(desc: Description) -> String {
+        let native = desc.to_native();
+        let json = Description::from_json(&desc.to_json()).unwrap();
+        assert_eq!(json.to_native(), native);
+        let yaml = Description::from_yaml(&desc.to_yaml()).unwrap();
+        assert_eq!(yaml.to_native(), native);
+        native
+    }
+
+    #[test]
+    fn tm_round_trip() {
+        let native = round_trip(Description::tm(TM).unwrap());
+        assert!(native.contains("#dialect = glob_keep\n"));
+        assert!(native.contains("#V = {x}\n"));
+        assert!(native.contains("#TEST ab => ab\n"));
+        assert!(native.contains("cp x_ xx rr cp\n"));
+        native.parse::<crate::automata::TuringMachine>().unwrap();
+    }
+
+    #[test]
+    fn pda_round_trip() {
+        let native = round_trip(Description::pda(PDA).unwrap());
+        assert!(native.contains("#TEST => false\n"));
+        assert!(native.contains("q _ 1 acc _\n"));
+        native.parse::<crate::automata::PushDownAutomata>().unwrap();
+    }
+
+    #[test]
+    fn dfa_round_trip() {
+        let desc = Description::dfa(DFA).unwrap();
+        assert!(desc.to_json().contains("\"kind\": \"dfa\""));
+        let native = round_trip(desc);
+        native
+            .parse::<crate::automata::DeterministicFiniteAutomata>()
+            .unwrap();
+    }
+
+    #[test]
+    fn bad_input() {
+        assert!(matches!(
+            Description::from_json("{\"kind\": \"nfa\"}"),
+            Err(Error::Json(_))
+        ));
+        assert!(matches!(
+            Description::from_yaml("kind: [tm"),
+            Err(Error::Yaml(_))
+        ));
+        assert!(matches!(Description::tm("#Q = {q}\n"), Err(Error::Decl(_))));
+    }
+}
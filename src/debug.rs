@@ -0,0 +1,179 @@
+use std::io::{BufRead, Write};
+
+use crate::automata::ArchState;
+
+const HELP: &str = "\
+step [N]           take N steps (default 1)
+back [N]           undo N steps (default 1)
+continue           run until a breakpoint or the machine halts
+break state Q      stop on entering state Q
+break head I X     stop when head I reads X (for a PDA, head 0 is the input, head 1 the stack)
+break step N       stop after step N
+delete ID          remove a breakpoint
+info               list breakpoints
+print              show the current configuration
+quit               leave the debugger
+an empty line repeats the last command";
+
+#[derive(Debug, Clone)]
+enum Breakpoint {
+    State(String),
+    Head(usize, char),
+    Step(usize),
+}
+
+impl Breakpoint {
+    /// `prev` is the state before the last step.
+    fn hit(&self, arch: &dyn ArchState, prev: &str) -> bool {
+        match self {
+            Breakpoint::State(q) => arch.state() == q && prev != q,
+            Breakpoint::Head(i, ch) => arch.heads().get(*i) == Some(ch),
+            Breakpoint::Step(n) => arch.steps() == *n,
+        }
+    }
+}
+
+impl std::fmt::Display for Breakpoint {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Breakpoint::State(q) => write!(f, "state {}", q),
+            Breakpoint::Head(i, ch) => write!(f, "head {} reads '{}'", i, ch),
+            Breakpoint::Step(n) => write!(f, "step {}", n),
+        }
+    }
+}
+
+struct Debugger {
+    arch: Box<dyn ArchState>,
+    breakpoints: Vec<Option<Breakpoint>>,
+    /// set once the machine halts
+    verdict: Option<String>,
+}
+
+impl Debugger {
+    /// Take up to `n` steps, stopping early on a breakpoint when `stop` is set.
+    fn run(&mut self, n: Option<usize>, stop: bool) {
+        if let Some(v) = &self.verdict {
+            println!("machine already halted: {}", v);
+            return;
+        }
+        let mut taken = 0;
+        while n.is_none_or(|n| taken < n) {
+            let prev = self.arch.state().to_owned();
+            let outcome = self.arch.step();
+            if outcome.halted() {
+                let v = outcome.to_string();
+                println!("machine halted: {}", v);
+                self.verdict = Some(v);
+                return;
+            }
+            taken += 1;
+            if stop {
+                for (id, b) in self.breakpoints.iter().enumerate() {
+                    if let Some(b) = b.as_ref().filter(|b| b.hit(self.arch.as_ref(), &prev)) {
+                        println!("breakpoint {} hit: {}", id, b);
+                        print!("{}", self.arch);
+                        return;
+                    }
+                }
+            }
+        }
+        print!("{}", self.arch);
+    }
+
+    fn back(&mut self, n: usize) {
+        let mut taken = 0;
+        while taken < n && self.arch.back() {
+            taken += 1;
+        }
+        if taken < n {
+            println!("back at the start of the run");
+        }
+        if taken > 0 {
+            self.verdict = None;
+        }
+        print!("{}", self.arch);
+    }
+
+    fn exec(&mut self, cmd: &[&str]) -> Result<bool, String> {
+        let number = |s: &str| {
+            s.parse::<usize>()
+                .map_err(|_| format!("not a number: {}", s))
+        };
+        let symbol = |s: &str| {
+            let mut chars = s.chars();
+            match (chars.next(), chars.next()) {
+                (Some(ch), None) => Ok(ch),
+                _ => Err(format!("not a single symbol: {}", s)),
+            }
+        };
+        match cmd {
+            ["s" | "step"] => self.run(Some(1), false),
+            ["s" | "step", n] => self.run(Some(number(n)?), true),
+            ["bk" | "back"] => self.back(1),
+            ["bk" | "back", n] => self.back(number(n)?),
+            ["c" | "continue"] => self.run(None, true),
+            ["b" | "break", rest @ ..] => {
+                let b = match rest {
+                    ["state", q] => Breakpoint::State(q.to_string()),
+                    ["head", i, ch] => Breakpoint::Head(number(i)?, symbol(ch)?),
+                    ["step", n] => Breakpoint::Step(number(n)?),
+                    _ => return Err("usage: break state Q | head I X | step N".to_owned()),
+                };
+                println!("breakpoint {}: {}", self.breakpoints.len(), b);
+                self.breakpoints.push(Some(b));
+            }
+            ["d" | "delete", id] => match self.breakpoints.get_mut(number(id)?) {
+                Some(b @ Some(_)) => *b = None,
+                _ => return Err(format!("no breakpoint {}", id)),
+            },
+            ["i" | "info"] => {
+                for (id, b) in self.breakpoints.iter().enumerate() {
+                    if let Some(b) = b {
+                        println!("{}: {}", id, b);
+                    }
+                }
+            }
+            ["p" | "print"] => print!("{}", self.arch),
+            ["h" | "help"] => println!("{}", HELP),
+            ["q" | "quit"] => return Ok(false),
+            _ => return Err("unknown command, try 'help'".to_owned()),
+        }
+        Ok(true)
+    }
+}
+
+/// Drive `arch`, whose input is already set, with commands read from stdin.
+pub fn repl(mut arch: Box<dyn ArchState>) {
+    arch.record();
+    let mut debugger = Debugger {
+        arch,
+        breakpoints: Vec::new(),
+        verdict: None,
+    };
+    print!("{}", debugger.arch);
+
+    let stdin = std::io::stdin();
+    let mut last = String::new();
+    loop {
+        print!("(fla) ");
+        std::io::stdout().flush().unwrap();
+        let mut line = String::new();
+        if stdin.lock().read_line(&mut line).unwrap() == 0 {
+            break;
+        }
+        if line.trim().is_empty() {
+            line = last.clone();
+        }
+        let cmd: Vec<&str> = line.split_whitespace().collect();
+        if cmd.is_empty() {
+            continue;
+        }
+        match debugger.exec(&cmd) {
+            Ok(true) => (),
+            Ok(false) => break,
+            Err(e) => println!("error: {}", e),
+        }
+        last = line;
+    }
+}
@@ -0,0 +1,182 @@
+use std::collections::HashSet;
+use std::fmt::Write;
+
+use crate::automata::{DeterministicFiniteAutomata, PushDownAutomata, TuringMachine};
+
+/// A state diagram, parallel rules merged into one edge.
+pub struct Graph {
+    q0: String,
+    states: Vec<String>,
+    finals: HashSet<String>,
+    /// ((from, to), one label per rule), in the order the edges first appear
+    edges: Vec<((String, String), Vec<String>)>,
+    /// separator between the labels of merged rules, as DOT source
+    sep: &'static str,
+}
+
+impl Graph {
+    fn new<'a>(
+        q0: &str,
+        Q: impl Iterator<Item = &'a String>,
+        F: &HashSet<String>,
+        sep: &'static str,
+    ) -> Self {
+        let mut states: Vec<String> = Q.cloned().collect();
+        states.sort();
+        Graph {
+            q0: q0.to_owned(),
+            states,
+            finals: F.clone(),
+            edges: Vec::new(),
+            sep,
+        }
+    }
+
+    fn add(&mut self, from: &str, to: &str, label: String) {
+        let key = (from.to_owned(), to.to_owned());
+        match self.edges.iter_mut().find(|(k, _)| *k == key) {
+            Some((_, labels)) => labels.push(label),
+            None => self.edges.push((key, vec![label])),
+        }
+    }
+
+    /// Render as DOT, drawing the states and edges of `trail` (the transitions a run took) in red.
+    pub fn render(&self, trail: &[(String, String)]) -> String {
+        let visited: HashSet<&str> = trail
+            .iter()
+            .flat_map(|(p, q)| [p.as_str(), q.as_str()])
+            .chain(match trail.is_empty() {
+                true => None,
+                false => Some(self.q0.as_str()),
+            })
+            .collect();
+        let taken: HashSet<&(String, String)> = trail.iter().collect();
+        let highlight = " color=red penwidth=2";
+
+        let mut out = String::new();
+        writeln!(out, "digraph {{").unwrap();
+        writeln!(out, "    rankdir=LR;").unwrap();
+        writeln!(out, "    node [shape=circle];").unwrap();
+        writeln!(out, "    \"\" [shape=none label=\"\" width=0 height=0];").unwrap();
+        writeln!(out, "    \"\" -> {};", quote(&self.q0)).unwrap();
+        for q in &self.states {
+            let mut attrs = String::new();
+            if self.finals.contains(q) {
+                attrs += " shape=doublecircle";
+            }
+            if visited.contains(q.as_str()) {
+                attrs += highlight;
+            }
+            match attrs.is_empty() {
+                true => writeln!(out, "    {};", quote(q)).unwrap(),
+                false => writeln!(out, "    {} [{}];", quote(q), attrs.trim()).unwrap(),
+            }
+        }
+        for (key @ (from, to), labels) in &self.edges {
+            let label: Vec<_> = labels.iter().map(|l| escape(l)).collect();
+            let mut attrs = format!("label=\"{}\"", label.join(self.sep));
+            if taken.contains(key) {
+                attrs += highlight;
+            }
+            writeln!(out, "    {} -> {} [{}];", quote(from), quote(to), attrs).unwrap();
+        }
+        writeln!(out, "}}").unwrap();
+        out
+    }
+}
+
+fn escape(s: &str) -> String {
+    s.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+fn quote(s: &str) -> String {
+    format!("\"{}\"", escape(s))
+}
+
+/// Edges labelled `a`, several symbols to the same state as `a,b`.
+pub fn dfa(dfa: &DeterministicFiniteAutomata) -> Graph {
+    let mut g = Graph::new(dfa.q0(), dfa.Q().iter(), dfa.F(), ",");
+    for ((q, a), p) in dfa.delta() {
+        g.add(q, p, a.to_string());
+    }
+    g
+}
+
+/// Edges labelled `a,X/β`, with `ε` for an epsilon move or an empty push.
+pub fn pda(pda: &PushDownAutomata) -> Graph {
+    let mut g = Graph::new(&pda.q0(), pda.Q().iter(), pda.F(), "\\n");
+    for ((q, a, X), (p, beta)) in pda.delta() {
+        let a = a.as_ref().map_or("ε".to_owned(), |a| a.to_string());
+        let beta = match beta.is_empty() {
+            true => "ε".to_owned(),
+            false => beta.iter().collect(),
+        };
+        g.add(q, p, format!("{},{}/{}", a, X, beta));
+    }
+    g
+}
+
+/// Edges labelled `X/Y,D`, each field with one cell per tape as in the source.
+pub fn tm(tm: &TuringMachine) -> Graph {
+    let mut g = Graph::new(tm.q0(), tm.Q().iter(), tm.F(), "\\n");
+    for ((q, X), (Y, D, p)) in tm.delta() {
+        let X: String = X.iter().map(|pat| pat.to_string()).collect();
+        let Y: String = Y.iter().collect();
+        let D: String = D.iter().map(|d| d.to_string()).collect();
+        g.add(q, p, format!("{}/{},{}", X, Y, D));
+    }
+    g
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn dfa_edges_merge_and_trail_is_red() {
+        let program = "\
+#Q = {q,r}
+#S = {a,b}
+#q0 = q
+#F = {r}
+
+q a r
+q b r
+r a r
+";
+        let g = dfa(&program.parse().unwrap());
+        let trail = [("q".to_owned(), "r".to_owned())];
+        assert_eq!(
+            g.render(&trail),
+            "\
+digraph {
+    rankdir=LR;
+    node [shape=circle];
+    \"\" [shape=none label=\"\" width=0 height=0];
+    \"\" -> \"q\";
+    \"q\" [color=red penwidth=2];
+    \"r\" [shape=doublecircle color=red penwidth=2];
+    \"q\" -> \"r\" [label=\"a,b\" color=red penwidth=2];
+    \"r\" -> \"r\" [label=\"a\"];
+}
+"
+        );
+    }
+
+    #[test]
+    fn pda_labels() {
+        let program = "\
+#Q = {q}
+#S = {a}
+#G = {z,\"}
+#q0 = q
+#z0 = z
+#F = {q}
+
+q a z q \"z
+q _ z q _
+";
+        let out = pda(&program.parse().unwrap()).render(&[]);
+        assert!(out.contains("\"q\" -> \"q\" [label=\"a,z/\\\"z\\nε,z/ε\"];"));
+    }
+}
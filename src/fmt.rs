@@ -0,0 +1,265 @@
+use std::cmp::Ordering;
+
+use crate::parse::{self, Position, Value};
+
+/// Canonical order of the declarations of each kind of machine.
+pub const DFA: &[&str] = &["Q", "S", "q0", "F"];
+pub const PDA: &[&str] = &["Q", "S", "G", "q0", "z0", "F"];
+pub const TM: &[&str] = &["N", "Q", "S", "G", "q0", "B", "F", "dialect", "V"];
+
+/// A declaration or rule, with the comment lines (`None` for a blank line) above it.
+struct Item {
+    lead: Vec<Option<String>>,
+    code: Code,
+    comment: Option<String>,
+}
+
+enum Code {
+    Decl(String, String),
+    Rule(Vec<String>),
+}
+
+/// Compare with digit runs as numbers, so `q2` comes before `q10`.
+fn natural(a: &str, b: &str) -> Ordering {
+    fn runs(s: &str) -> Vec<&str> {
+        let mut runs = Vec::new();
+        let mut start = 0;
+        for (i, ch) in s.char_indices().skip(1) {
+            let prev = s[..i].chars().last().unwrap();
+            if prev.is_ascii_digit() != ch.is_ascii_digit() {
+                runs.push(&s[start..i]);
+                start = i;
+            }
+        }
+        runs.push(&s[start..]);
+        runs
+    }
+    let digits = |s: &str| s.starts_with(|ch: char| ch.is_ascii_digit());
+    for (x, y) in runs(a).into_iter().zip(runs(b)) {
+        let ord = match (digits(x), digits(y)) {
+            (true, true) => {
+                let (x, y) = (x.trim_start_matches('0'), y.trim_start_matches('0'));
+                x.len().cmp(&y.len()).then(x.cmp(y))
+            }
+            _ => x.cmp(y),
+        };
+        if ord != Ordering::Equal {
+            return ord;
+        }
+    }
+    a.len().cmp(&b.len()).then(a.cmp(b))
+}
+
+/// The parser's warnings about a description.
+type Warnings = Vec<(Position, parse::Warning)>;
+
+/// Re-emit a description: the declarations in the order of `decls` with sorted sets,
+/// then the `#TEST` cases as they were, then the rules grouped by source state and aligned
+/// in columns.
+/// Comments move with the declaration or rule they sit on or above.
+/// Also returns the parser's warnings, a set member repeated there is written once.
+pub fn format(
+    s: &str,
+    decls: &[&str],
+    nr_trans_item: usize,
+) -> Result<(String, Warnings), Vec<(Position, parse::ParseError)>> {
+    let warnings = parse::parse(s, nr_trans_item)?.warnings;
+
+    let mut items = Vec::new();
+    let mut lead = Vec::new();
+    for line in s.lines() {
+        let (code, comment) = match line.split_once(';') {
+            Some((code, comment)) => (code.trim(), Some(format!(";{}", comment.trim_end()))),
+            None => (line.trim(), None),
+        };
+        if code.is_empty() {
+            lead.push(comment);
+            continue;
+        }
+        let c = parse::parse(code, nr_trans_item)?;
+        let code = match (c.store.into_iter().next(), c.trans.into_iter().next()) {
+            _ if !c.tests.is_empty() => {
+                let case = &c.tests[0];
+                let test = match case.input.as_str() {
+                    "" => format!("=> {}", case.expected),
+                    input => format!("{} => {}", input, case.expected),
+                };
+                Code::Decl("TEST".to_owned(), test)
+            }
+            (Some((k, (_, Value::Str(v)))), _) => Code::Decl(k, v),
+            (Some((k, (_, Value::Set(items)))), _) => {
+                let mut items: Vec<String> = items.into_iter().map(|(_, item)| item).collect();
+                items.sort_by(|a, b| natural(a, b));
+                items.dedup();
+                Code::Decl(k, format!("{{{}}}", items.join(",")))
+            }
+            (None, Some((_, rule))) => Code::Rule(rule),
+            (None, None) => unreachable!(),
+        };
+        items.push(Item {
+            lead: std::mem::take(&mut lead),
+            code,
+            comment,
+        });
+    }
+
+    // comments above the first item, up to a blank line, belong to the file
+    let mut preamble = Vec::new();
+    if let Some(first) = items.first_mut() {
+        if let Some(k) = first.lead.iter().rposition(Option::is_none) {
+            preamble = first.lead.drain(..=k).collect();
+        }
+    }
+
+    let rank = |k: &str| decls.iter().position(|d| *d == k).unwrap_or(decls.len());
+    let (mut decl_items, rules): (Vec<Item>, Vec<Item>) = items
+        .into_iter()
+        .partition(|item| matches!(item.code, Code::Decl(..)));
+    decl_items.sort_by_key(|item| match &item.code {
+        Code::Decl(k, _) => rank(k),
+        Code::Rule(_) => unreachable!(),
+    });
+
+    let mut states: Vec<&str> = Vec::new();
+    for item in &rules {
+        if let Code::Rule(rule) = &item.code {
+            if !states.contains(&rule[0].as_str()) {
+                states.push(&rule[0]);
+            }
+        }
+    }
+    let mut widths = vec![0; nr_trans_item];
+    for item in &rules {
+        if let Code::Rule(rule) = &item.code {
+            for (w, field) in widths.iter_mut().zip(rule) {
+                *w = (*w).max(field.chars().count());
+            }
+        }
+    }
+    let group = |item: &Item| match &item.code {
+        Code::Rule(rule) => states.iter().position(|q| *q == rule[0]).unwrap(),
+        Code::Decl(..) => unreachable!(),
+    };
+    let mut grouped: Vec<&Item> = rules.iter().collect();
+    grouped.sort_by_key(|item| group(item));
+
+    let mut out: Vec<String> = Vec::new();
+    let blank = |out: &mut Vec<String>| {
+        if out.last().is_some_and(|line| !line.is_empty()) {
+            out.push(String::new());
+        }
+    };
+    let comments = |out: &mut Vec<String>, lines: &[Option<String>]| {
+        for line in lines {
+            match line {
+                Some(comment) => out.push(comment.to_owned()),
+                None => blank(out),
+            }
+        }
+    };
+
+    comments(&mut out, &preamble);
+    blank(&mut out);
+    for item in decl_items.iter().chain(grouped) {
+        if matches!(item.code, Code::Rule(_)) && out.last().is_some_and(|l| l.starts_with('#')) {
+            blank(&mut out);
+        }
+        comments(&mut out, &item.lead);
+        let mut line = match &item.code {
+            Code::Decl(k, v) if k == "TEST" => format!("#{} {}", k, v),
+            Code::Decl(k, v) => format!("#{} = {}", k, v),
+            Code::Rule(rule) => rule
+                .iter()
+                .zip(&widths)
+                .map(|(field, w)| format!("{:<w$}", field, w = w))
+                .collect::<Vec<_>>()
+                .join(" "),
+        };
+        match &item.comment {
+            Some(comment) => line = format!("{} {}", line, comment),
+            None => line.truncate(line.trim_end().len()),
+        }
+        out.push(line);
+    }
+    comments(&mut out, &lead);
+
+    while out.last().is_some_and(String::is_empty) {
+        out.pop();
+    }
+    let out = out.iter().map(|line| line.to_owned() + "\n").collect();
+    Ok((out, warnings))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MESSY: &str = "\
+; copies nothing, formatted badly
+
+#F={acc}
+#Q = {q10,q2,acc,q1}
+  #N=1
+#S = {b,a}
+#G = {a,b,_,a}
+#B = _
+; start here
+#q0 = q1
+
+; from q2
+q2 a a r q10   ; keep
+q1 * * r q2
+#TEST ab => ab
+q10 _ _ * acc
+q1 _ _ * acc
+; trailing
+";
+
+    const TIDY: &str = "\
+; copies nothing, formatted badly
+
+#N = 1
+#Q = {acc,q1,q2,q10}
+#S = {a,b}
+#G = {_,a,b}
+; start here
+#q0 = q1
+#B = _
+#F = {acc}
+#TEST ab => ab
+
+; from q2
+q2  a a r q10 ; keep
+q1  * * r q2
+q1  _ _ * acc
+q10 _ _ * acc
+; trailing
+";
+
+    #[test]
+    fn formats_and_reports_merged_members() {
+        let (out, warnings) = format(MESSY, TM, 5).unwrap();
+        assert_eq!(out, TIDY);
+        assert_eq!(warnings.len(), 1);
+        assert_eq!((warnings[0].0.line(), warnings[0].0.col + 1), (7, 13));
+    }
+
+    #[test]
+    fn idempotent() {
+        let (out, warnings) = format(TIDY, TM, 5).unwrap();
+        assert_eq!(out, TIDY);
+        assert!(warnings.is_empty());
+    }
+
+    #[test]
+    fn natural_order() {
+        let mut states = ["q10", "q2", "q1", "a", "q02"];
+        states.sort_by(|a, b| natural(a, b));
+        assert_eq!(states, ["a", "q1", "q2", "q02", "q10"]);
+    }
+
+    #[test]
+    fn refuses_broken_input() {
+        assert!(format("#Q = {q\nq a\n", PDA, 5).is_err());
+    }
+}
@@ -0,0 +1,726 @@
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::fmt::Write;
+
+use crate::analysis::{ahead, overlap, subtract, Cube};
+use crate::automata::tm::Direction;
+use crate::automata::{DeterministicFiniteAutomata, PushDownAutomata, TuringMachine};
+use crate::convert::{Description, Dfa, DfaRule, Pda, PdaRule, Tm, TmRule};
+use crate::parse::valid_state_char;
+
+/// JFLAP features with no counterpart here.
+#[derive(Debug, Clone)]
+pub enum Error {
+    Xml(String),
+    /// a `<type>` other than `fa`, `pda` and `turing`
+    Type(String),
+    /// a building-block TM
+    Block,
+    NoInitialState,
+    /// a missing element, or one with an unreadable value
+    Element(String),
+    /// two states whose names clash, or an id without a state
+    State(String),
+    /// `_` used as an ordinary symbol, it stands for the blank or for "nothing" here
+    Blank,
+    /// a read or pop of more than one symbol
+    MultiChar(String),
+    /// an empty read in a finite automaton
+    Lambda,
+    /// a TM symbol JFLAP would read as its wildcard
+    Tilde,
+    /// spelling the patterns out would take this many transitions
+    TooLarge(usize),
+    /// a TM without an `#S` note, JFLAP has no input alphabet
+    Alphabet,
+}
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Error::Xml(e) => write!(f, "not a well-formed XML file: {}", e),
+            Error::Type(t) => write!(f, "JFLAP machines of type '{}' are not supported", t),
+            Error::Block => write!(f, "building blocks are not supported"),
+            Error::NoInitialState => write!(f, "the machine has no initial state"),
+            Error::Element(tag) => write!(f, "missing or unreadable <{}>", tag),
+            Error::State(q) => write!(f, "state '{}' is undefined or defined twice", q),
+            Error::Blank => write!(
+                f,
+                "'_' is used as a symbol, but it stands for the blank or for nothing here"
+            ),
+            Error::MultiChar(s) => write!(f, "'{}' reads or pops more than one symbol", s),
+            Error::Lambda => write!(f, "a finite automaton with λ transitions is not a DFA"),
+            Error::Tilde => write!(f, "'~' is a tape symbol, but JFLAP reads it as a wildcard"),
+            Error::TooLarge(n) => write!(
+                f,
+                "spelling out the patterns would take {} transitions, try --dialect glob_blank",
+                n
+            ),
+            Error::Alphabet => write!(
+                f,
+                "JFLAP does not record which tape symbols are input, \
+                 add a note like `#S = {{a,b}}` to the machine"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+/// The `<type>`, and `<automaton>` element of a JFLAP file.
+fn automaton<'a>(
+    doc: &'a roxmltree::Document<'a>,
+) -> Result<(String, roxmltree::Node<'a, 'a>), Error> {
+    let structure = doc.root_element();
+    let kind = child(structure, "type").ok_or(Error::Element("type".to_owned()))?;
+    let auto = structure
+        .children()
+        .find(|n| n.has_tag_name("automaton"))
+        .unwrap_or(structure);
+    if auto.descendants().any(|n| n.has_tag_name("block")) {
+        return Err(Error::Block);
+    }
+    Ok((kind, auto))
+}
+
+/// Text of the child `tag`, empty for `<tag/>`.
+fn child(node: roxmltree::Node, tag: &str) -> Option<String> {
+    node.children()
+        .find(|n| n.has_tag_name(tag))
+        .map(|n| n.text().unwrap_or("").trim().to_owned())
+}
+
+/// Text of the child `tag` of tape `tape` (counting from 1), `None` for a single tape.
+fn cell(node: roxmltree::Node, tag: &str, tape: Option<usize>) -> Result<String, Error> {
+    node.children()
+        .find(|n| {
+            n.has_tag_name(tag)
+                && match tape {
+                    Some(i) => n.attribute("tape") == Some(&i.to_string()),
+                    None => true,
+                }
+        })
+        .map(|n| n.text().unwrap_or("").trim().to_owned())
+        .ok_or(Error::Element(tag.to_owned()))
+}
+
+/// At most one symbol, `None` for an empty field.
+fn symbol(s: &str) -> Result<Option<char>, Error> {
+    let mut chars = s.chars();
+    match (chars.next(), chars.next()) {
+        (None, _) => Ok(None),
+        (Some('_'), None) => Err(Error::Blank),
+        (Some(ch), None) => Ok(Some(ch)),
+        _ => Err(Error::MultiChar(s.to_owned())),
+    }
+}
+
+struct States {
+    /// id to name
+    names: HashMap<String, String>,
+    q0: String,
+    F: Vec<String>,
+}
+
+impl States {
+    /// States keep their JFLAP names where those are valid here, and become `q<id>` otherwise.
+    fn read(auto: roxmltree::Node) -> Result<Self, Error> {
+        let mut names = HashMap::new();
+        let mut taken = HashSet::new();
+        let mut q0 = None;
+        let mut F = Vec::new();
+        for state in auto.children().filter(|n| n.has_tag_name("state")) {
+            let id = state
+                .attribute("id")
+                .ok_or(Error::Element("id".to_owned()))?;
+            let name = match state.attribute("name") {
+                Some(name) if !name.is_empty() && name.chars().all(valid_state_char) => {
+                    name.to_owned()
+                }
+                _ => format!("q{}", id),
+            };
+            if !taken.insert(name.clone()) {
+                return Err(Error::State(name));
+            }
+            if state.children().any(|n| n.has_tag_name("initial")) {
+                q0 = Some(name.clone());
+            }
+            if state.children().any(|n| n.has_tag_name("final")) {
+                F.push(name.clone());
+            }
+            names.insert(id.to_owned(), name);
+        }
+        Ok(States {
+            names,
+            q0: q0.ok_or(Error::NoInitialState)?,
+            F,
+        })
+    }
+
+    fn Q(&self) -> Vec<String> {
+        let mut Q: Vec<_> = self.names.values().cloned().collect();
+        Q.sort();
+        Q
+    }
+
+    /// The source and target of a transition.
+    fn ends(&self, t: roxmltree::Node) -> Result<(String, String), Error> {
+        let name = |tag| {
+            let id = child(t, tag).ok_or(Error::Element(tag.to_owned()))?;
+            self.names.get(&id).cloned().ok_or(Error::State(id))
+        };
+        Ok((name("from")?, name("to")?))
+    }
+}
+
+/// The input alphabet from a note `#S = {a,b}`, which `render` writes since JFLAP has
+/// no place for it.
+fn alphabet(auto: roxmltree::Node) -> Result<Option<BTreeSet<char>>, Error> {
+    let Some(text) = auto
+        .children()
+        .filter(|n| n.has_tag_name("note"))
+        .filter_map(|n| child(n, "text"))
+        .find(|text| text.starts_with("#S"))
+    else {
+        return Ok(None);
+    };
+    let unreadable = || Error::Element("note".to_owned());
+    let set = text["#S".len()..]
+        .trim_start()
+        .strip_prefix('=')
+        .map(str::trim)
+        .and_then(|v| v.strip_prefix('{'))
+        .and_then(|v| v.strip_suffix('}'))
+        .ok_or_else(unreadable)?;
+    set.split(',')
+        .map(str::trim)
+        .filter(|a| !a.is_empty())
+        .map(|a| symbol(a)?.ok_or_else(unreadable))
+        .collect::<Result<_, _>>()
+        .map(Some)
+}
+
+fn sorted(symbols: BTreeSet<char>) -> Vec<String> {
+    symbols.into_iter().map(String::from).collect()
+}
+
+/// Map a JFLAP file onto a description, which still has to be checked by the machine parser.
+pub fn import(xml: &str) -> Result<Description, Error> {
+    let doc = roxmltree::Document::parse(xml).map_err(|e| Error::Xml(e.to_string()))?;
+    let (kind, auto) = automaton(&doc)?;
+    let states = States::read(auto)?;
+    let noted = alphabet(auto)?;
+    let transitions: Vec<_> = auto
+        .children()
+        .filter(|n| n.has_tag_name("transition"))
+        .collect();
+
+    match kind.as_str() {
+        "fa" => {
+            let mut S = BTreeSet::new();
+            let mut delta = Vec::new();
+            for t in transitions {
+                let (from, to) = states.ends(t)?;
+                let read = cell(t, "read", None)?;
+                let a = symbol(&read)?.ok_or(Error::Lambda)?;
+                S.insert(a);
+                delta.push(DfaRule {
+                    from,
+                    read: a.to_string(),
+                    to,
+                });
+            }
+            Ok(Description::Dfa(Dfa {
+                Q: states.Q(),
+                S: sorted(noted.unwrap_or(S)),
+                q0: states.q0,
+                F: states.F,
+                delta,
+                tests: Vec::new(),
+            }))
+        }
+        "pda" => {
+            // JFLAP starts with `Z` on the stack, and an empty pop leaves the stack alone
+            let mut S = BTreeSet::new();
+            let mut G = BTreeSet::from(['Z']);
+            let mut rules = Vec::new();
+            for t in transitions {
+                let (from, to) = states.ends(t)?;
+                let a = symbol(&cell(t, "read", None)?)?;
+                let X = symbol(&cell(t, "pop", None)?)?;
+                let push = cell(t, "push", None)?;
+                if push.contains('_') {
+                    return Err(Error::Blank);
+                }
+                S.extend(a);
+                G.extend(X);
+                G.extend(push.chars());
+                rules.push((from, a, X, to, push));
+            }
+            let mut delta = Vec::new();
+            for (from, a, X, to, push) in rules {
+                let pops = match X {
+                    Some(X) => vec![(X, push.clone())],
+                    None => G.iter().map(|X| (*X, format!("{}{}", push, X))).collect(),
+                };
+                for (X, push) in pops {
+                    delta.push(PdaRule {
+                        from: from.clone(),
+                        input: a.unwrap_or('_').to_string(),
+                        pop: X.to_string(),
+                        to: to.clone(),
+                        push: if push.is_empty() {
+                            "_".to_owned()
+                        } else {
+                            push
+                        },
+                    });
+                }
+            }
+            Ok(Description::Pda(Pda {
+                Q: states.Q(),
+                S: sorted(noted.unwrap_or(S)),
+                G: sorted(G),
+                q0: states.q0,
+                z0: "Z".to_owned(),
+                F: states.F,
+                delta,
+                tests: Vec::new(),
+            }))
+        }
+        "turing" => {
+            let N = match child(doc.root_element(), "tapes") {
+                Some(n) => n.parse().map_err(|_| Error::Element("tapes".to_owned()))?,
+                None => 1,
+            };
+            let tapes: Vec<Option<usize>> = match N {
+                1 => vec![None],
+                _ => (1..=N).map(Some).collect(),
+            };
+            // JFLAP's blank is an empty cell, `~` reads anything and writes back what it read
+            let field = |s: &str| match s {
+                "~" => Ok('*'),
+                s => Ok(symbol(s)?.unwrap_or('_')),
+            };
+            // tape 0 also reads what the machine wrote there, so it does not tell the input
+            let S = noted.ok_or(Error::Alphabet)?;
+            let mut G = BTreeSet::from(['_']);
+            G.extend(&S);
+            let mut wildcard = false;
+            let mut delta = Vec::new();
+            for t in transitions {
+                let (from, to) = states.ends(t)?;
+                let (mut read, mut write, mut dir) = (String::new(), String::new(), String::new());
+                for tape in &tapes {
+                    let r = field(&cell(t, "read", *tape)?)?;
+                    let w = field(&cell(t, "write", *tape)?)?;
+                    let d = match cell(t, "move", *tape)?.as_str() {
+                        "L" => 'l',
+                        "R" => 'r',
+                        "S" => '*',
+                        d => return Err(Error::Element(format!("move {}", d))),
+                    };
+                    for ch in [r, w] {
+                        match ch {
+                            '*' => wildcard = true,
+                            ch => {
+                                G.insert(ch);
+                            }
+                        }
+                    }
+                    read.push(r);
+                    write.push(w);
+                    dir.push(d);
+                }
+                delta.push(TmRule {
+                    from,
+                    read,
+                    write,
+                    dir,
+                    to,
+                });
+            }
+            Ok(Description::Tm(Tm {
+                N,
+                Q: states.Q(),
+                S: sorted(S),
+                G: sorted(G),
+                q0: states.q0,
+                B: "_".to_owned(),
+                F: states.F,
+                dialect: wildcard.then(|| "relaxed".to_owned()),
+                V: None,
+                delta,
+                tests: Vec::new(),
+            }))
+        }
+        kind => Err(Error::Type(kind.to_owned())),
+    }
+}
+
+fn escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+/// `<tag>text</tag>`, or `<tag/>` for empty text.
+fn element(tag: &str, tape: Option<usize>, text: &str) -> String {
+    let attr = tape.map_or(String::new(), |i| format!(" tape=\"{}\"", i));
+    match text.is_empty() {
+        true => format!("<{}{}/>", tag, attr),
+        false => format!("<{}{}>{}</{}>", tag, attr, escape(text), tag),
+    }
+}
+
+/// A JFLAP file of `kind`, transitions given as (from, to, inner elements).
+fn render(
+    kind: &str,
+    tapes: Option<usize>,
+    S: &HashSet<char>,
+    Q: &HashSet<String>,
+    q0: &str,
+    F: &HashSet<String>,
+    transitions: &[(&str, &str, Vec<String>)],
+) -> String {
+    let mut Q: Vec<_> = Q.iter().collect();
+    Q.sort();
+    let ids: HashMap<&str, usize> = Q.iter().enumerate().map(|(i, q)| (q.as_str(), i)).collect();
+
+    let mut out = String::new();
+    writeln!(
+        out,
+        "<?xml version=\"1.0\" encoding=\"UTF-8\" standalone=\"no\"?><!--Created with fla.-->"
+    )
+    .unwrap();
+    writeln!(out, "<structure>").unwrap();
+    writeln!(out, "\t<type>{}</type>", kind).unwrap();
+    if let Some(n) = tapes {
+        writeln!(out, "\t<tapes>{}</tapes>", n).unwrap();
+    }
+    writeln!(out, "\t<automaton>").unwrap();
+    for (i, q) in Q.iter().enumerate() {
+        writeln!(out, "\t\t<state id=\"{}\" name=\"{}\">", i, q).unwrap();
+        writeln!(out, "\t\t\t<x>{}.0</x>", 100 + 150 * (i % 6)).unwrap();
+        writeln!(out, "\t\t\t<y>{}.0</y>", 100 + 150 * (i / 6)).unwrap();
+        if q.as_str() == q0 {
+            writeln!(out, "\t\t\t<initial/>").unwrap();
+        }
+        if F.contains(*q) {
+            writeln!(out, "\t\t\t<final/>").unwrap();
+        }
+        writeln!(out, "\t\t</state>").unwrap();
+    }
+    let mut S: Vec<_> = S.iter().map(|a| a.to_string()).collect();
+    S.sort();
+    writeln!(out, "\t\t<note>").unwrap();
+    writeln!(
+        out,
+        "\t\t\t{}",
+        element("text", None, &format!("#S = {{{}}}", S.join(",")))
+    )
+    .unwrap();
+    writeln!(out, "\t\t\t<x>100.0</x>").unwrap();
+    writeln!(out, "\t\t\t<y>30.0</y>").unwrap();
+    writeln!(out, "\t\t</note>").unwrap();
+    for (from, to, inner) in transitions {
+        writeln!(out, "\t\t<transition>").unwrap();
+        writeln!(out, "\t\t\t<from>{}</from>", ids[from]).unwrap();
+        writeln!(out, "\t\t\t<to>{}</to>", ids[to]).unwrap();
+        for e in inner {
+            writeln!(out, "\t\t\t{}", e).unwrap();
+        }
+        writeln!(out, "\t\t</transition>").unwrap();
+    }
+    writeln!(out, "\t</automaton>").unwrap();
+    writeln!(out, "</structure>").unwrap();
+    out
+}
+
+/// The cubes each rule actually applies to: its own minus those of the rules of the same
+/// state that take precedence.
+fn winning(rules: &[(&str, Cube)], last_wins: bool) -> Vec<Vec<Cube>> {
+    rules
+        .iter()
+        .enumerate()
+        .map(|(j, (q, cube))| {
+            rules[ahead(rules.len(), j, last_wins)]
+                .iter()
+                .filter(|(p, _)| p == q)
+                .fold(vec![cube.clone()], |residual, (_, before)| {
+                    residual.iter().flat_map(|r| subtract(r, before)).collect()
+                })
+        })
+        .collect()
+}
+
+/// The tuples in `cube`, `None` for a position where `wild` accepts the whole set.
+fn tuples(cube: &Cube, wild: impl Fn(&BTreeSet<char>) -> bool) -> Vec<Vec<Option<char>>> {
+    cube.iter().fold(vec![Vec::new()], |tuples, symbols| {
+        let options: Vec<Option<char>> = match wild(symbols) {
+            true => vec![None],
+            false => symbols.iter().copied().map(Some).collect(),
+        };
+        tuples
+            .iter()
+            .flat_map(|t| {
+                options.iter().map(move |ch| {
+                    let mut t = t.clone();
+                    t.push(*ch);
+                    t
+                })
+            })
+            .collect()
+    })
+}
+
+pub fn dfa(dfa: &DeterministicFiniteAutomata) -> String {
+    let transitions: Vec<_> = dfa
+        .delta()
+        .iter()
+        .map(|((q, a), p)| {
+            (
+                q.as_str(),
+                p.as_str(),
+                vec![element("read", None, &a.to_string())],
+            )
+        })
+        .collect();
+    render(
+        "fa",
+        None,
+        dfa.S(),
+        dfa.Q(),
+        dfa.q0(),
+        dfa.F(),
+        &transitions,
+    )
+}
+
+/// JFLAP runs a PDA nondeterministically, so rules are cut down to the tuples they win on
+/// here. It still also tries epsilon moves where this PDA would read input instead, and
+/// always starts with `Z` on the stack, `z0` and `Z` swap names if they differ.
+pub fn pda(pda: &PushDownAutomata) -> String {
+    let z0 = pda.z0();
+    let rename = |ch: char| match ch {
+        ch if ch == z0 => 'Z',
+        'Z' => z0,
+        ch => ch,
+    };
+    let rules: Vec<_> = pda
+        .delta()
+        .iter()
+        .map(|((q, a, X), _)| (q.as_str(), overlap::pda_cube(pda, a, X)))
+        .collect();
+    let mut transitions = Vec::new();
+    for (((q, a, _), (p, beta)), cubes) in pda.delta().iter().zip(winning(&rules, true)) {
+        let push: String = beta.iter().map(|ch| rename(*ch)).collect();
+        for t in cubes.iter().flat_map(|c| tuples(c, |_| false)) {
+            let t: Vec<char> = t.into_iter().flatten().collect();
+            let read = match a {
+                Some(_) => t[0].to_string(),
+                None => String::new(),
+            };
+            transitions.push((
+                q.as_str(),
+                p.as_str(),
+                vec![
+                    element("read", None, &read),
+                    element("pop", None, &rename(t[1]).to_string()),
+                    element("push", None, &push),
+                ],
+            ));
+        }
+    }
+    render(
+        "pda",
+        None,
+        pda.S(),
+        pda.Q(),
+        &pda.q0(),
+        pda.F(),
+        &transitions,
+    )
+}
+
+/// Transitions `tm` may export to before `tm` gives up.
+const LIMIT: usize = 100_000;
+
+/// Patterns are spelled out symbol by symbol, except that a tape matching every symbol
+/// including the blank reads JFLAP's `~`. The blank becomes an empty cell.
+pub fn tm(tm: &TuringMachine) -> Result<String, Error> {
+    if tm.G().contains(&'~') {
+        return Err(Error::Tilde);
+    }
+    let B = tm.B();
+    let G: BTreeSet<char> = tm.G().iter().copied().collect();
+    let wild = |symbols: &BTreeSet<char>| *symbols == G;
+    let cell = |ch: Option<char>| match ch {
+        None => "~".to_owned(),
+        Some(ch) if ch == B => String::new(),
+        Some(ch) => ch.to_string(),
+    };
+    let tapes: Vec<Option<usize>> = match tm.N() {
+        1 => vec![None],
+        n => (1..=n).map(Some).collect(),
+    };
+    let rules: Vec<_> = tm
+        .delta()
+        .iter()
+        .map(|((q, pats), _)| (q.as_str(), overlap::cube(tm, pats)))
+        .collect();
+    let residuals = winning(&rules, false);
+
+    let count: usize = residuals
+        .iter()
+        .flatten()
+        .map(|c| {
+            c.iter()
+                .map(|x| if wild(x) { 1 } else { x.len() })
+                .product::<usize>()
+        })
+        .sum();
+    if count > LIMIT {
+        return Err(Error::TooLarge(count));
+    }
+
+    let mut transitions = Vec::new();
+    for (((q, _), (Y, D, p)), cubes) in tm.delta().iter().zip(residuals) {
+        for t in cubes.iter().flat_map(|c| tuples(c, wild)) {
+            let mut inner = Vec::new();
+            for (((tape, r), w), d) in tapes.iter().zip(&t).zip(Y).zip(D) {
+                let w = if *w == '*' { *r } else { Some(*w) };
+                let d = match d {
+                    Direction::Left => "L",
+                    Direction::Right => "R",
+                    Direction::Stay => "S",
+                };
+                inner.push(element("read", *tape, &cell(*r)));
+                inner.push(element("write", *tape, &cell(w)));
+                inner.push(element("move", *tape, d));
+            }
+            transitions.push((q.as_str(), p.as_str(), inner));
+        }
+    }
+    let tapes = match tm.N() {
+        1 => None,
+        n => Some(n),
+    };
+    Ok(render(
+        "turing",
+        tapes,
+        tm.S(),
+        tm.Q(),
+        tm.q0(),
+        tm.F(),
+        &transitions,
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::automata::{ArchState, DfaArchState, PdaArchState, TmArchState};
+    use crate::cases::{self, shortlex};
+
+    /// Both machines give the same outcome on every word up to 6 symbols.
+    fn same(a: impl Fn() -> Box<dyn ArchState>, b: impl Fn() -> Box<dyn ArchState>) {
+        for w in shortlex(&['a', 'b'], 6) {
+            let x = cases::run(a().as_mut(), &w, 1000);
+            let y = cases::run(b().as_mut(), &w, 1000);
+            assert_eq!(x, y, "on {:?}", w);
+        }
+    }
+
+    #[test]
+    fn dfa_round_trip() {
+        let program = "\
+#Q = {even,odd}
+#S = {a,b}
+#q0 = even
+#F = {even}
+
+even a odd
+even b even
+odd a even
+";
+        let dfa: DeterministicFiniteAutomata = program.parse().unwrap();
+        let back: DeterministicFiniteAutomata = import(&super::dfa(&dfa))
+            .unwrap()
+            .to_native()
+            .parse()
+            .unwrap();
+        same(
+            || Box::new(DfaArchState::new(dfa.clone())),
+            || Box::new(DfaArchState::new(back.clone())),
+        );
+    }
+
+    #[test]
+    fn pda_round_trip_keeps_the_last_matching_rule() {
+        let program = "\
+#Q = {q0,q1,q2,dead,accept}
+#S = {a,b}
+#G = {1,z}
+#q0 = q0
+#z0 = z
+#F = {accept}
+
+q0 a z q1 1z
+q1 a 1 q1 11
+q1 b 1 dead _
+q1 b 1 q2 _
+q2 b 1 q2 _
+q2 _ z accept _
+";
+        let pda: PushDownAutomata = program.parse().unwrap();
+        let xml = super::pda(&pda);
+        let back: PushDownAutomata = import(&xml).unwrap().to_native().parse().unwrap();
+        assert_eq!(back.delta().len(), pda.delta().len() - 1);
+        same(
+            || Box::new(PdaArchState::new(pda.clone())),
+            || Box::new(PdaArchState::new(back.clone())),
+        );
+    }
+
+    #[test]
+    fn tm_round_trip_keeps_the_first_matching_rule() {
+        let program = "\
+#Q = {q,acc}
+#S = {a,b}
+#G = {a,b,_}
+#q0 = q
+#B = _
+#F = {acc}
+#N = 1
+
+q a b r q
+q * * r q
+q b a r q
+q _ _ l acc
+";
+        let tm: TuringMachine = program.parse().unwrap();
+        let xml = super::tm(&tm).unwrap();
+        // without the note there is no telling input from tape symbols
+        let (head, rest) = xml.split_once("<note>").unwrap();
+        let bare = format!("{}{}", head, rest.split_once("</note>").unwrap().1);
+        assert!(matches!(import(&bare), Err(Error::Alphabet)));
+        let desc = import(&xml).unwrap();
+        let Description::Tm(t) = &desc else {
+            panic!("not a TM");
+        };
+        assert_eq!(t.S, ["a", "b"]);
+        let back: TuringMachine = desc.to_native().parse().unwrap();
+        same(
+            || Box::new(TmArchState::new(tm.clone())),
+            || Box::new(TmArchState::new(back.clone())),
+        );
+    }
+
+    #[test]
+    fn import_errors() {
+        assert!(matches!(import("<structure>"), Err(Error::Xml(_))));
+        let mealy = "<structure><type>mealy</type><automaton>\
+            <state id=\"0\" name=\"q0\"><initial/></state></automaton></structure>";
+        assert!(matches!(import(mealy), Err(Error::Type(_))));
+    }
+}
@@ -0,0 +1,11 @@
+//! Parsers and simulators for the DFA, PDA and TM descriptions of the NJU FLA course.
+//!
+//! The binary compiles these modules itself, along with the tooling built on the parts
+//! that are only `pub(crate)` here.
+
+#[allow(non_snake_case, non_camel_case_types, dead_code)]
+pub mod automata;
+#[allow(dead_code)]
+mod cases;
+#[allow(dead_code)]
+pub mod parse;
@@ -0,0 +1,319 @@
+use std::collections::HashMap;
+
+use lsp_server::{Connection, ErrorCode, Message, Notification, Request, Response};
+use lsp_types::{
+    CompletionItem, CompletionItemKind, CompletionOptions, CompletionParams, Diagnostic,
+    DiagnosticSeverity, DidChangeTextDocumentParams, DidCloseTextDocumentParams,
+    DidOpenTextDocumentParams, GotoDefinitionParams, Hover, HoverContents, HoverParams,
+    HoverProviderCapability, Location, MarkupContent, MarkupKind, NumberOrString, OneOf,
+    PublishDiagnosticsParams, Range, ReferenceParams, ServerCapabilities,
+    TextDocumentPositionParams, TextDocumentSyncCapability, TextDocumentSyncKind, Uri,
+};
+use serde_json::Value as Json;
+
+use crate::analysis;
+use crate::automata::{DeterministicFiniteAutomata, PushDownAutomata, TuringMachine};
+use crate::parse::{self, Value};
+use crate::Mode;
+
+type Error = Box<dyn std::error::Error + Send + Sync>;
+
+/// UTF-16 column of byte offset `col` in `line`, as LSP counts them.
+fn utf16(line: &str, col: usize) -> u32 {
+    line.get(..col).unwrap_or(line).encode_utf16().count() as u32
+}
+
+fn range(row: usize, line: &str, col: usize, len: usize) -> Range {
+    let start = lsp_types::Position::new(row as u32, utf16(line, col));
+    let end = lsp_types::Position::new(row as u32, utf16(line, col + len));
+    Range::new(start, end)
+}
+
+/// Where the states of a document are declared and used, built line by line
+/// so that one broken line does not hide the rest.
+#[derive(Default)]
+struct Index {
+    /// members of `#Q`
+    states: Vec<(String, Range)>,
+    /// members of `#S` and `#G`
+    symbols: Vec<String>,
+    /// every occurrence of a state name, in `#Q`, `#q0`, `#F` or a rule
+    refs: Vec<(String, Range)>,
+    /// (source state, rule as written)
+    rules: Vec<(String, String)>,
+}
+
+impl Index {
+    fn new(mode: &Mode, text: &str) -> Self {
+        let nr_trans_item = mode.nr_trans_item();
+        let to = match mode {
+            Mode::Dfa => 2,
+            Mode::Pda => 3,
+            Mode::Tm => 4,
+        };
+        let mut index = Index::default();
+        for (row, line) in text.lines().enumerate() {
+            let code = line.split_once(';').map_or(line, |(code, _)| code);
+            let Ok(c) = parse::parse(code, nr_trans_item) else {
+                continue;
+            };
+            for (k, (value, v)) in c.store {
+                match (k.as_str(), v) {
+                    ("Q" | "F", Value::Set(items)) => {
+                        for (pos, item) in items {
+                            let r = range(row, line, pos.col, item.len());
+                            if k == "Q" {
+                                index.states.push((item.clone(), r));
+                            }
+                            index.refs.push((item, r));
+                        }
+                    }
+                    ("S" | "G", Value::Set(items)) => {
+                        for (_, item) in items {
+                            if !index.symbols.contains(&item) {
+                                index.symbols.push(item);
+                            }
+                        }
+                    }
+                    ("q0", Value::Str(q)) => {
+                        let r = range(row, line, value.col, value.len);
+                        index.refs.push((q, r));
+                    }
+                    _ => (),
+                }
+            }
+            if !c.trans.is_empty() {
+                let fields = parse::fields(code);
+                for k in [0, to] {
+                    let (span, q) = &fields[k];
+                    index
+                        .refs
+                        .push((q.clone(), range(row, line, span.start, span.len())));
+                }
+                index
+                    .rules
+                    .push((fields[0].1.clone(), code.trim().to_owned()));
+            }
+        }
+        index
+    }
+
+    /// The state name under the cursor.
+    fn at(&self, pos: lsp_types::Position) -> Option<&(String, Range)> {
+        self.refs.iter().find(|(_, r)| {
+            r.start.line == pos.line
+                && r.start.character <= pos.character
+                && pos.character <= r.end.character
+        })
+    }
+
+    fn definition(&self, q: &str) -> Option<Range> {
+        self.states.iter().find(|(p, _)| p == q).map(|(_, r)| *r)
+    }
+}
+
+/// Errors of the machine parser and, if it parses, the lint warnings.
+fn diagnostics(mode: &Mode, text: &str) -> Vec<Diagnostic> {
+    let lines: Vec<&str> = text.lines().collect();
+    let diagnostic = |pos: &parse::Position, severity, message: String| {
+        let line = lines.get(pos.row).copied().unwrap_or("");
+        Diagnostic {
+            range: range(pos.row, line, pos.col, pos.len),
+            severity: Some(severity),
+            source: Some("fla".to_owned()),
+            message,
+            ..Default::default()
+        }
+    };
+    let error = |pos: &parse::Position, e: &dyn parse::Diagnostic| Diagnostic {
+        code: Some(NumberOrString::String(e.code().to_owned())),
+        ..diagnostic(pos, DiagnosticSeverity::ERROR, e.to_string())
+    };
+    let warnings = |findings: Vec<analysis::lint::Finding>| {
+        findings
+            .iter()
+            .map(|(pos, lint)| diagnostic(pos, DiagnosticSeverity::WARNING, lint.to_string()))
+            .collect()
+    };
+    match mode {
+        Mode::Dfa => match text.parse::<DeterministicFiniteAutomata>() {
+            Ok(dfa) => warnings(analysis::lint::dfa(&dfa)),
+            Err(errors) => errors.iter().map(|(pos, e)| error(pos, e)).collect(),
+        },
+        Mode::Pda => match text.parse::<PushDownAutomata>() {
+            Ok(pda) => warnings(analysis::lint::pda(&pda)),
+            Err(errors) => errors.iter().map(|(pos, e)| error(pos, e)).collect(),
+        },
+        Mode::Tm => match TuringMachine::parse_with(text, None) {
+            Ok(tm) => warnings(analysis::lint::tm(&tm)),
+            Err(errors) => errors.iter().map(|(pos, e)| error(pos, e)).collect(),
+        },
+    }
+}
+
+fn definition(index: &Index, uri: Uri, p: TextDocumentPositionParams) -> Option<Location> {
+    let (q, _) = index.at(p.position)?;
+    Some(Location::new(uri, index.definition(q)?))
+}
+
+fn references(index: &Index, uri: Uri, p: ReferenceParams) -> Vec<Location> {
+    let Some((q, _)) = index.at(p.text_document_position.position) else {
+        return Vec::new();
+    };
+    let def = index.definition(q);
+    let include = p.context.include_declaration;
+    index
+        .refs
+        .iter()
+        .filter(|(p, r)| p == q && (include || Some(*r) != def))
+        .map(|(_, r)| Location::new(uri.clone(), *r))
+        .collect()
+}
+
+fn hover(index: &Index, p: HoverParams) -> Option<Hover> {
+    let (q, r) = index.at(p.text_document_position_params.position)?;
+    let rules: Vec<&str> = index
+        .rules
+        .iter()
+        .filter(|(p, _)| p == q)
+        .map(|(_, rule)| rule.as_str())
+        .collect();
+    let value = match rules.is_empty() {
+        true => format!("state `{}` has no rules", q),
+        false => format!("```\n{}\n```", rules.join("\n")),
+    };
+    Some(Hover {
+        contents: HoverContents::Markup(MarkupContent {
+            kind: MarkupKind::Markdown,
+            value,
+        }),
+        range: Some(*r),
+    })
+}
+
+fn completion(index: &Index, _: CompletionParams) -> Vec<CompletionItem> {
+    let item = |label: &str, kind, detail: &str| CompletionItem {
+        label: label.to_owned(),
+        kind: Some(kind),
+        detail: Some(detail.to_owned()),
+        ..Default::default()
+    };
+    let states = index
+        .states
+        .iter()
+        .map(|(q, _)| item(q, CompletionItemKind::ENUM_MEMBER, "state"));
+    let symbols = index
+        .symbols
+        .iter()
+        .map(|a| item(a, CompletionItemKind::CONSTANT, "symbol"));
+    states.chain(symbols).collect()
+}
+
+fn respond(docs: &HashMap<String, Index>, req: Request) -> Response {
+    let id = req.id.clone();
+    let doc = |uri: &Uri| docs.get(uri.as_str());
+    let result: Result<Json, _> = match req.method.as_str() {
+        "textDocument/definition" => req
+            .extract::<GotoDefinitionParams>("textDocument/definition")
+            .map(|(_, p)| {
+                let p = p.text_document_position_params;
+                let uri = p.text_document.uri.clone();
+                doc(&uri).and_then(|index| definition(index, uri, p))
+            })
+            .map(|r| serde_json::to_value(r).unwrap()),
+        "textDocument/references" => req
+            .extract::<ReferenceParams>("textDocument/references")
+            .map(|(_, p)| {
+                let uri = p.text_document_position.text_document.uri.clone();
+                doc(&uri).map(|index| references(index, uri, p))
+            })
+            .map(|r| serde_json::to_value(r).unwrap()),
+        "textDocument/hover" => req
+            .extract::<HoverParams>("textDocument/hover")
+            .map(|(_, p)| {
+                let uri = &p.text_document_position_params.text_document.uri;
+                doc(uri).and_then(|index| hover(index, p))
+            })
+            .map(|r| serde_json::to_value(r).unwrap()),
+        "textDocument/completion" => req
+            .extract::<CompletionParams>("textDocument/completion")
+            .map(|(_, p)| {
+                let uri = &p.text_document_position.text_document.uri;
+                doc(uri).map(|index| completion(index, p))
+            })
+            .map(|r| serde_json::to_value(r).unwrap()),
+        method => {
+            return Response::new_err(
+                id,
+                ErrorCode::MethodNotFound as i32,
+                format!("unsupported request {}", method),
+            )
+        }
+    };
+    match result {
+        Ok(result) => Response::new_ok(id, result),
+        Err(e) => Response::new_err(id, ErrorCode::InvalidParams as i32, format!("{:?}", e)),
+    }
+}
+
+/// Serve the language server protocol on stdin/stdout until the client shuts it down.
+pub fn serve() -> Result<(), Error> {
+    let (conn, io_threads) = Connection::stdio();
+    let capabilities = ServerCapabilities {
+        text_document_sync: Some(TextDocumentSyncCapability::Kind(TextDocumentSyncKind::FULL)),
+        definition_provider: Some(OneOf::Left(true)),
+        references_provider: Some(OneOf::Left(true)),
+        hover_provider: Some(HoverProviderCapability::Simple(true)),
+        completion_provider: Some(CompletionOptions::default()),
+        ..Default::default()
+    };
+    conn.initialize(serde_json::to_value(capabilities)?)?;
+
+    let mut docs: HashMap<String, Index> = HashMap::new();
+    for msg in &conn.receiver {
+        let (uri, text) = match msg {
+            Message::Request(req) => {
+                if conn.handle_shutdown(&req)? {
+                    break;
+                }
+                conn.sender.send(Message::Response(respond(&docs, req)))?;
+                continue;
+            }
+            Message::Response(_) => continue,
+            Message::Notification(note) => match note.method.as_str() {
+                "textDocument/didOpen" => {
+                    let p: DidOpenTextDocumentParams = note.extract("textDocument/didOpen")?;
+                    (p.text_document.uri, p.text_document.text)
+                }
+                "textDocument/didChange" => {
+                    let mut p: DidChangeTextDocumentParams =
+                        note.extract("textDocument/didChange")?;
+                    // full sync, the last change is the whole document
+                    let Some(change) = p.content_changes.pop() else {
+                        continue;
+                    };
+                    (p.text_document.uri, change.text)
+                }
+                "textDocument/didClose" => {
+                    let p: DidCloseTextDocumentParams = note.extract("textDocument/didClose")?;
+                    docs.remove(p.text_document.uri.as_str());
+                    continue;
+                }
+                _ => continue,
+            },
+        };
+        let Some(mode) = Mode::detect(uri.as_str()) else {
+            continue;
+        };
+        let params = PublishDiagnosticsParams::new(uri.clone(), diagnostics(&mode, &text), None);
+        conn.sender.send(Message::Notification(Notification::new(
+            "textDocument/publishDiagnostics".to_owned(),
+            params,
+        )))?;
+        docs.insert(uri.as_str().to_owned(), Index::new(&mode, &text));
+    }
+    // the writer thread only stops once the connection is gone
+    drop(conn);
+    io_threads.join()?;
+    Ok(())
+}
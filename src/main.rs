@@ -1,163 +1,962 @@
 #[allow(non_snake_case, non_camel_case_types)]
+mod analysis;
+#[allow(non_snake_case, non_camel_case_types)]
 mod automata;
+mod cases;
+#[allow(non_snake_case)]
+mod convert;
+mod debug;
+#[allow(non_snake_case)]
+mod dot;
+mod fmt;
+#[allow(non_snake_case)]
+mod jff;
+mod lsp;
 mod parse;
+mod trace;
+mod tui;
 
 use clap::Parser;
 use std::io::Read;
 
+/// The kind of machine a description is for, told by its file extension.
+pub enum Mode {
+    Dfa,
+    Pda,
+    Tm,
+}
+
+impl Mode {
+    /// Fields in a transition of this kind of machine.
+    pub fn nr_trans_item(&self) -> usize {
+        match self {
+            Mode::Dfa => 3,
+            Mode::Pda | Mode::Tm => 5,
+        }
+    }
+
+    pub fn detect(program: &str) -> Option<Self> {
+        if program.ends_with(".dfa") {
+            Some(Mode::Dfa)
+        } else if program.ends_with(".pda") {
+            Some(Mode::Pda)
+        } else if program.ends_with(".tm") {
+            Some(Mode::Tm)
+        } else {
+            None
+        }
+    }
+}
+
 #[derive(clap::Parser, Debug)]
-#[command(version, about, long_about = None)]
+#[command(
+    version,
+    about,
+    long_about = None,
+    args_conflicts_with_subcommands = true,
+    subcommand_negates_reqs = true
+)]
 struct Arguments {
+    #[command(subcommand)]
+    command: Option<Command>,
     #[arg(short, long, action = clap::ArgAction::SetTrue, help = "show step by step execution trace")]
     verbose: bool,
+    #[arg(
+        long,
+        value_enum,
+        default_value_t,
+        help = "trace format, json prints one record per step and a final verdict"
+    )]
+    trace_format: trace::TraceFormat,
+    #[arg(
+        long,
+        global = true,
+        help = "how '*' in TM rules is read: 2024 (default), glob_blank, glob_keep or relaxed"
+    )]
+    dialect: Option<automata::tm::Dialect>,
+    #[arg(
+        long,
+        global = true,
+        help = "stop listing errors in a machine description after this many"
+    )]
+    max_errors: Option<usize>,
     #[arg(
         id = "machine",
+        required = true,
         help = "dfa (*.dfa), pda (*.pda) or tm (*.tm) description"
     )]
-    program: String,
-    #[arg(id = "input")]
-    input: String,
+    program: Option<String>,
+    #[arg(id = "input", required_unless_present = "inputs_file")]
+    input: Option<String>,
+    #[arg(
+        long,
+        conflicts_with_all = ["input", "verbose", "trace_format"],
+        help = "run each line of this file, or of stdin if '-', as an input and print one result per line"
+    )]
+    inputs_file: Option<String>,
 }
 
-enum Mode {
-    Dfa,
-    Pda,
-    Tm,
+#[derive(clap::Subcommand, Debug)]
+enum Command {
+    /// Report unreachable states, unused symbols, shadowed rules and other suspicious definitions
+    Lint {
+        #[arg(
+            id = "machine",
+            help = "dfa (*.dfa), pda (*.pda) or tm (*.tm) description"
+        )]
+        program: String,
+    },
+    /// Step through a run interactively, with breakpoints
+    Debug {
+        #[arg(
+            id = "machine",
+            help = "dfa (*.dfa), pda (*.pda) or tm (*.tm) description"
+        )]
+        program: String,
+        #[arg(id = "input")]
+        input: String,
+    },
+    /// Animate a run full-screen in the terminal
+    Tui {
+        #[arg(
+            id = "machine",
+            help = "dfa (*.dfa), pda (*.pda) or tm (*.tm) description"
+        )]
+        program: String,
+        #[arg(id = "input")]
+        input: String,
+    },
+    /// Print the state diagram in Graphviz DOT
+    Dot {
+        #[arg(
+            id = "machine",
+            help = "dfa (*.dfa), pda (*.pda) or tm (*.tm) description"
+        )]
+        program: String,
+        #[arg(long, help = "highlight the path taken on this input")]
+        input: Option<String>,
+        #[arg(
+            long,
+            default_value_t = 10000,
+            help = "stop following the path after this many steps"
+        )]
+        max_steps: usize,
+    },
+    /// Convert between the native .dfa/.tm/.pda syntax, JSON (*.json), YAML (*.yaml, *.yml) and JFLAP (*.jff)
+    Convert {
+        #[arg(id = "from", help = "machine description to read")]
+        from: String,
+        #[arg(id = "to", help = "file to write, its extension picks the format")]
+        to: String,
+    },
+    /// Rewrite a description in canonical layout: ordered declarations, sorted sets, aligned rules grouped by state
+    Fmt {
+        #[arg(
+            id = "machine",
+            help = "dfa (*.dfa), pda (*.pda) or tm (*.tm) description"
+        )]
+        program: String,
+        #[arg(long, help = "only check, exit with 1 if the file is not formatted")]
+        check: bool,
+    },
+    /// Run a language server for .dfa/.pda/.tm files on stdin and stdout
+    Lsp,
+    /// Run the `#TEST input => expected` cases of a machine and those of a cases file, one
+    /// `input => expected` per line, and report those that fail
+    Test {
+        #[arg(
+            id = "machine",
+            help = "dfa (*.dfa), pda (*.pda) or tm (*.tm) description"
+        )]
+        program: String,
+        #[arg(
+            id = "cases",
+            help = "expected results: true/false for a DFA or PDA, tape 0 for a TM, or `illegal input`"
+        )]
+        cases: Option<String>,
+        #[arg(
+            long,
+            default_value_t = 100000,
+            help = "give up on a case after this many steps"
+        )]
+        max_steps: usize,
+    },
+    /// Run two machines on every input up to a length and report the first one they
+    /// disagree on
+    Compare {
+        #[arg(help = "dfa (*.dfa), pda (*.pda) or tm (*.tm) description")]
+        a: String,
+        #[arg(help = "dfa (*.dfa), pda (*.pda) or tm (*.tm) description")]
+        b: String,
+        #[arg(long, default_value_t = 6, help = "longest input to try")]
+        max_len: usize,
+        #[arg(
+            long,
+            default_value_t = 100000,
+            help = "give up on an input after this many steps"
+        )]
+        max_steps: usize,
+    },
+    /// List the inputs up to a length a DFA or PDA accepts, or what a TM outputs for each
+    Lang {
+        #[arg(
+            id = "machine",
+            help = "dfa (*.dfa), pda (*.pda) or tm (*.tm) description"
+        )]
+        program: String,
+        #[arg(long, default_value_t = 6, help = "longest input to try")]
+        max_len: usize,
+        #[arg(
+            long,
+            default_value_t = 100000,
+            help = "give up on an input after this many steps, for a PDA or TM"
+        )]
+        max_steps: usize,
+    },
+    /// List the (state, symbols) combinations reachable states have no rule for
+    CoverageGaps {
+        #[arg(
+            id = "machine",
+            help = "dfa (*.dfa), pda (*.pda) or tm (*.tm) description"
+        )]
+        program: String,
+    },
+    /// Show rules that overlap on some symbol tuple or are shadowed by other rules
+    Overlap {
+        #[arg(id = "machine", help = "pda (*.pda) or tm (*.tm) description")]
+        program: String,
+    },
 }
 
-fn main() {
-    let args = Arguments::parse();
+fn banner(s: &str) -> String {
+    "=".repeat(20) + " " + s + " " + &("=".repeat(20))
+}
+
+/// The kind of machine at `path`, or exit with an error for an unknown extension.
+fn mode(path: &str) -> Mode {
+    Mode::detect(path).unwrap_or_else(|| {
+        eprintln!(
+            "{}: unknown machine type, expected a .dfa, .pda or .tm file",
+            path
+        );
+        std::process::exit(1)
+    })
+}
 
+fn read_program(path: &str) -> String {
     let mut program = String::new();
-    std::fs::File::open(&args.program)
+    std::fs::File::open(path)
         .expect("failed to open machine description")
         .read_to_string(&mut program)
         .expect("failed to read machine description");
+    program
+}
 
-    fn banner(s: &str) -> String {
-        "=".repeat(20) + " " + s + " " + &("=".repeat(20))
+/// Unwrap a parsed machine description, or report its errors, at most `max_errors`
+/// of them, and exit.
+fn load<M, E>(parsed: Result<M, Vec<(parse::Position, E)>>, max_errors: Option<usize>) -> M
+where
+    E: parse::Diagnostic,
+{
+    match parsed {
+        Ok(m) => m,
+        Err(errors) => {
+            let shown = max_errors.unwrap_or(usize::MAX).min(errors.len());
+            eprintln!("{}", banner("ERR"));
+            for (k, (pos, err)) in errors.iter().take(shown).enumerate() {
+                if k > 0 {
+                    eprintln!();
+                }
+                eprintln!("error[{}]: {}", err.code(), err);
+                eprint!("{}", pos);
+            }
+            if shown < errors.len() {
+                eprintln!();
+                eprintln!("note: {} more errors not shown", errors.len() - shown);
+            }
+            eprintln!("{}", banner("END"));
+            std::process::exit(1);
+        }
     }
-    let banner_run = banner("RUN");
-    let banner_err = banner("ERR");
-    let banner_end = banner("END");
-    let banner_split = "-".repeat(45);
+}
 
-    if args.verbose {
-        println!("Input: {}", args.input);
+/// Show the parser's warnings about a loaded machine on stderr.
+fn warn(warnings: &[(parse::Position, parse::Warning)]) {
+    for (pos, w) in warnings {
+        eprintln!("warning: {}", w);
+        eprint!("{}", pos);
     }
+}
+
+fn load_dfa(program: &str, max_errors: Option<usize>) -> automata::DeterministicFiniteAutomata {
+    let dfa: automata::DeterministicFiniteAutomata = load(program.parse(), max_errors);
+    warn(&dfa.source().warnings);
+    dfa
+}
 
-    let mode: Mode;
+fn load_pda(program: &str, max_errors: Option<usize>) -> automata::PushDownAutomata {
+    let pda: automata::PushDownAutomata = load(program.parse(), max_errors);
+    warn(&pda.source().warnings);
+    pda
+}
 
-    let mut arch_state: Box<dyn automata::ArchState>;
+fn load_tm(
+    program: &str,
+    dialect: Option<automata::tm::Dialect>,
+    max_errors: Option<usize>,
+) -> automata::TuringMachine {
+    let tm = load(
+        automata::TuringMachine::parse_with(program, dialect),
+        max_errors,
+    );
+    warn(&tm.source().warnings);
+    tm
+}
 
-    if args.program.ends_with(".dfa") {
-        mode = Mode::Dfa;
-        unimplemented!()
-    } else if args.program.ends_with(".pda") {
-        mode = Mode::Pda;
-        let machine: automata::PushDownAutomata = match program.parse() {
-            Ok(m) => m,
-            Err((pos, err)) => {
-                eprintln!("{}", banner_err);
-                eprint!("{}", pos);
-                eprintln!("{:?}", err);
-                eprintln!("{}", banner_end);
+fn lint(path: &str, dialect: Option<automata::tm::Dialect>, max_errors: Option<usize>) {
+    let program = read_program(path);
+    // the parser's warnings are among the findings, so load without showing them
+    let findings = match mode(path) {
+        Mode::Dfa => analysis::lint::dfa(&load(program.parse(), max_errors)),
+        Mode::Pda => analysis::lint::pda(&load(program.parse(), max_errors)),
+        Mode::Tm => analysis::lint::tm(&load(
+            automata::TuringMachine::parse_with(&program, dialect),
+            max_errors,
+        )),
+    };
+    for (pos, lint) in findings {
+        println!("warning: {}", lint);
+        print!("{}", pos);
+    }
+}
+
+fn overlap(path: &str, dialect: Option<automata::tm::Dialect>, max_errors: Option<usize>) {
+    let program = read_program(path);
+    let (reports, source) = match mode(path) {
+        Mode::Dfa => {
+            eprintln!(
+                "error: a DFA has at most one rule per state and symbol, no rules can overlap"
+            );
+            std::process::exit(1);
+        }
+        Mode::Pda => {
+            let pda = load_pda(&program, max_errors);
+            (analysis::overlap::pda(&pda), pda.source().clone())
+        }
+        Mode::Tm => {
+            let tm = load_tm(&program, dialect, max_errors);
+            (analysis::overlap::tm(&tm), tm.source().clone())
+        }
+    };
+    let rule = |i: usize| {
+        let pos = &source.rules[i];
+        format!("line {}: {}", pos.line(), pos.inst.trim())
+    };
+    for report in reports {
+        if report.overlaps.is_empty() && report.shadowed.is_empty() {
+            continue;
+        }
+        println!("state {}", report.state);
+        for o in report.overlaps {
+            println!("  overlap on {}:", o.example);
+            println!("    {}", rule(o.first));
+            println!("    {}", rule(o.second));
+        }
+        for s in report.shadowed {
+            match s.example {
+                Some(e) => println!(
+                    "  unreachable, every tuple it matches (e.g. {}) is taken by other rules:",
+                    e
+                ),
+                None => println!("  unreachable, matches no symbols at all:"),
+            }
+            println!("    {}", rule(s.rule));
+            for i in s.by {
+                println!("  by {}", rule(i));
+            }
+        }
+    }
+}
+
+fn coverage_gaps(path: &str, dialect: Option<automata::tm::Dialect>, max_errors: Option<usize>) {
+    let program = read_program(path);
+    match mode(path) {
+        Mode::Dfa => {
+            for (q, a) in analysis::coverage::dfa(&load_dfa(&program, max_errors)) {
+                println!("{} {}", q, a);
+            }
+        }
+        Mode::Pda => {
+            for (q, a, x) in analysis::coverage::pda(&load_pda(&program, max_errors)) {
+                println!("{} {} {}", q, a.unwrap_or('_'), x);
+            }
+        }
+        Mode::Tm => {
+            for (q, gaps) in analysis::coverage::tm(&load_tm(&program, dialect, max_errors)) {
+                let n: usize = gaps
+                    .iter()
+                    .map(|c| c.iter().map(|x| x.len()).product::<usize>())
+                    .sum();
+                println!("state {}: {} missing", q, n);
+                for c in gaps {
+                    println!("  {}", analysis::show(&c));
+                }
+            }
+        }
+    }
+}
+
+fn main() {
+    let args = Arguments::parse();
+
+    match args.command {
+        Some(Command::Lint { program }) => lint(&program, args.dialect, args.max_errors),
+        Some(Command::CoverageGaps { program }) => {
+            coverage_gaps(&program, args.dialect, args.max_errors)
+        }
+        Some(Command::Overlap { program }) => overlap(&program, args.dialect, args.max_errors),
+        Some(Command::Debug { program, input }) => {
+            debug::repl(start(&program, &input, true, args.dialect, args.max_errors))
+        }
+        Some(Command::Dot {
+            program,
+            input,
+            max_steps,
+        }) => dot(
+            &program,
+            input.as_deref(),
+            max_steps,
+            args.dialect,
+            args.max_errors,
+        ),
+        Some(Command::Convert { from, to }) => convert(&from, &to, args.dialect, args.max_errors),
+        Some(Command::Fmt { program, check }) => fmt(&program, check, args.max_errors),
+        Some(Command::Test {
+            program,
+            cases,
+            max_steps,
+        }) => test(
+            &program,
+            cases.as_deref(),
+            max_steps,
+            args.dialect,
+            args.max_errors,
+        ),
+        Some(Command::Compare {
+            a,
+            b,
+            max_len,
+            max_steps,
+        }) => compare(&a, &b, max_len, max_steps, args.dialect, args.max_errors),
+        Some(Command::Lang {
+            program,
+            max_len,
+            max_steps,
+        }) => lang(&program, max_len, max_steps, args.dialect, args.max_errors),
+        Some(Command::Lsp) => {
+            if let Err(e) = lsp::serve() {
+                eprintln!("error: {}", e);
                 std::process::exit(1);
             }
-        };
-        arch_state = Box::new(automata::PdaArchState::new(machine));
-    } else if args.program.ends_with(".tm") {
-        mode = Mode::Tm;
-        let machine: automata::TuringMachine = match program.parse() {
-            Ok(m) => m,
-            Err((pos, err)) => {
-                eprintln!("{}", banner_err);
-                eprint!("{}", pos);
-                eprintln!("{:?}", err);
-                eprintln!("{}", banner_end);
+        }
+        Some(Command::Tui { program, input }) => {
+            if let Err(e) = tui::show(start(&program, &input, true, args.dialect, args.max_errors))
+            {
+                eprintln!("error: {}", e);
                 std::process::exit(1);
             }
-        };
-        arch_state = Box::new(automata::TmArchState::new(machine));
+        }
+        None if args.inputs_file.is_some() => batch(
+            &args.program.unwrap(),
+            &args.inputs_file.unwrap(),
+            args.dialect,
+            args.max_errors,
+        ),
+        None => run(
+            &args.program.unwrap(),
+            &args.input.unwrap(),
+            args.verbose,
+            args.trace_format,
+            args.dialect,
+            args.max_errors,
+        ),
+    }
+}
+
+/// Exit with a message for a description `convert` cannot handle.
+fn convert_err(msg: String) -> ! {
+    eprintln!("{}", banner("ERR"));
+    eprintln!("error: {}", msg.trim_end());
+    eprintln!("{}", banner("END"));
+    std::process::exit(1);
+}
+
+fn convert(
+    from: &str,
+    to: &str,
+    dialect: Option<automata::tm::Dialect>,
+    max_errors: Option<usize>,
+) {
+    let program = read_program(from);
+    let desc = if from.ends_with(".json") {
+        convert::Description::from_json(&program)
+    } else if from.ends_with(".yaml") || from.ends_with(".yml") {
+        convert::Description::from_yaml(&program)
+    } else if from.ends_with(".jff") {
+        Ok(jff::import(&program).unwrap_or_else(|e| convert_err(e.to_string())))
+    } else {
+        match mode(from) {
+            Mode::Dfa => {
+                load_dfa(&program, max_errors);
+                convert::Description::dfa(&program)
+            }
+            Mode::Pda => {
+                load_pda(&program, max_errors);
+                convert::Description::pda(&program)
+            }
+            Mode::Tm => {
+                load_tm(&program, dialect, max_errors);
+                convert::Description::tm(&program)
+            }
+        }
+    };
+    let desc = desc.unwrap_or_else(|e| convert_err(e.to_string()));
+
+    // whatever it came from, the description has to make a valid machine; a native
+    // source showed its warnings when it was loaded above
+    let native = desc.to_native();
+    let imported = Mode::detect(from).is_none();
+    let checked = |warnings: &[(parse::Position, parse::Warning)]| {
+        if imported {
+            warn(warnings);
+        }
+    };
+    let to_jff = to.ends_with(".jff");
+    let jff = match desc {
+        convert::Description::Dfa(_) => {
+            let dfa: automata::DeterministicFiniteAutomata = load(native.parse(), max_errors);
+            checked(&dfa.source().warnings);
+            to_jff.then(|| jff::dfa(&dfa))
+        }
+        convert::Description::Tm(_) => {
+            let tm = load(
+                automata::TuringMachine::parse_with(&native, dialect),
+                max_errors,
+            );
+            checked(&tm.source().warnings);
+            to_jff.then(|| jff::tm(&tm).unwrap_or_else(|e| convert_err(e.to_string())))
+        }
+        convert::Description::Pda(_) => {
+            let pda: automata::PushDownAutomata = load(native.parse(), max_errors);
+            checked(&pda.source().warnings);
+            to_jff.then(|| jff::pda(&pda))
+        }
+    };
+
+    let out = if to.ends_with(".json") {
+        desc.to_json()
+    } else if to.ends_with(".yaml") || to.ends_with(".yml") {
+        desc.to_yaml()
+    } else if let Some(jff) = jff {
+        jff
     } else {
-        panic!("Unknown machine type!");
+        match (mode(to), &desc) {
+            (Mode::Dfa, convert::Description::Dfa(_))
+            | (Mode::Tm, convert::Description::Tm(_))
+            | (Mode::Pda, convert::Description::Pda(_)) => native,
+            _ => convert_err(format!(
+                "{} and {} are for different kinds of machine",
+                from, to
+            )),
+        }
+    };
+    std::fs::write(to, out).expect("failed to write machine description");
+}
+
+fn fmt(path: &str, check: bool, max_errors: Option<usize>) {
+    let program = read_program(path);
+    let mode = mode(path);
+    let decls = match mode {
+        Mode::Dfa => fmt::DFA,
+        Mode::Pda => fmt::PDA,
+        Mode::Tm => fmt::TM,
+    };
+    let (formatted, warnings) = load(
+        fmt::format(&program, decls, mode.nr_trans_item()),
+        max_errors,
+    );
+    warn(&warnings);
+    if formatted == program {
+        return;
+    }
+    if check {
+        eprintln!("{} is not formatted", path);
+        std::process::exit(1);
+    }
+    std::fs::write(path, formatted).expect("failed to write machine description");
+}
+
+/// An input or result for display, `ε` for the empty string.
+fn show(s: &str) -> String {
+    match s {
+        "" => "ε".to_owned(),
+        s => s.to_owned(),
+    }
+}
+
+/// A machine that yields fresh runtimes, and its input symbols in order.
+type Factory = (Box<dyn Fn() -> Box<dyn automata::ArchState>>, Vec<char>);
+
+fn machine(
+    path: &str,
+    dialect: Option<automata::tm::Dialect>,
+    max_errors: Option<usize>,
+) -> Factory {
+    let program = read_program(path);
+    let (new, symbols): (Box<dyn Fn() -> Box<dyn automata::ArchState>>, _) = match mode(path) {
+        Mode::Dfa => {
+            let dfa = load_dfa(&program, max_errors);
+            let symbols = dfa.S().clone();
+            (
+                Box::new(move || Box::new(automata::DfaArchState::new(dfa.clone()))),
+                symbols,
+            )
+        }
+        Mode::Pda => {
+            let pda = load_pda(&program, max_errors);
+            let symbols = pda.S().clone();
+            (
+                Box::new(move || Box::new(automata::PdaArchState::new(pda.clone()))),
+                symbols,
+            )
+        }
+        Mode::Tm => {
+            let tm = load_tm(&program, dialect, max_errors);
+            let symbols = tm.S().clone();
+            (
+                Box::new(move || Box::new(automata::TmArchState::new(tm.clone()))),
+                symbols,
+            )
+        }
+    };
+    let mut symbols: Vec<char> = symbols.into_iter().collect();
+    symbols.sort();
+    (new, symbols)
+}
+
+fn test(
+    path: &str,
+    cases_path: Option<&str>,
+    max_steps: usize,
+    dialect: Option<automata::tm::Dialect>,
+    max_errors: Option<usize>,
+) {
+    let (new, _) = machine(path, dialect, max_errors);
+    // the machine parsed, so its `#TEST` lines did too
+    let mut cases = parse::parse_all(&read_program(path), mode(path).nr_trans_item())
+        .0
+        .tests;
+    if let Some(cases_path) = cases_path {
+        cases.extend(load(cases::parse(&read_program(cases_path)), max_errors));
+    }
+    if cases.is_empty() {
+        eprintln!("no test cases, add `#TEST input => expected` lines or a cases file");
+        std::process::exit(1);
+    }
+    let results: Vec<(cases::Case, cases::Outcome)> = cases
+        .into_iter()
+        .map(|case| {
+            let outcome = cases::run(new().as_mut(), &case.input, max_steps);
+            (case, outcome)
+        })
+        .collect();
+
+    let width = |title: &str, column: fn(&cases::Case) -> &str| {
+        results
+            .iter()
+            .map(|(case, _)| show(column(case)).chars().count())
+            .fold(title.len(), usize::max)
+    };
+    let w_input = width("input", |case| &case.input);
+    let w_expected = width("expected", |case| &case.expected);
+    println!(
+        "{:<4}  {:<w_input$}  {:<w_expected$}  actual",
+        "", "input", "expected"
+    );
+    let mut failed = 0;
+    for (case, outcome) in &results {
+        let pass = case.passes(outcome);
+        let actual = outcome.to_string();
+        println!(
+            "{}  {:<w_input$}  {:<w_expected$}  {}",
+            if pass { "PASS" } else { "FAIL" },
+            show(&case.input),
+            show(&case.expected),
+            show(&actual)
+        );
+        if pass {
+            continue;
+        }
+        failed += 1;
+        // point at where the tapes part
+        if matches!(outcome, cases::Outcome::Output(_)) && case.expected != cases::ILLEGAL {
+            let same = case
+                .expected
+                .chars()
+                .zip(actual.chars())
+                .take_while(|(a, b)| a == b)
+                .count();
+            let indent = 4 + 2 + w_input + 2 + w_expected + 2 + same;
+            println!("{}^", " ".repeat(indent));
+        }
+    }
+    println!();
+    println!("{} passed, {} failed", results.len() - failed, failed);
+    if failed > 0 {
+        std::process::exit(1);
+    }
+}
+
+fn compare(
+    a: &str,
+    b: &str,
+    max_len: usize,
+    max_steps: usize,
+    dialect: Option<automata::tm::Dialect>,
+    max_errors: Option<usize>,
+) {
+    let (new_a, symbols_a) = machine(a, dialect, max_errors);
+    let (new_b, symbols_b) = machine(b, dialect, max_errors);
+    // inputs only one machine declares should be illegal on the other
+    let mut symbols: Vec<char> = symbols_a.into_iter().chain(symbols_b).collect();
+    symbols.sort();
+    symbols.dedup();
+
+    let mut tried = 0;
+    let mut timeouts = Vec::new();
+    for input in cases::shortlex(&symbols, max_len) {
+        tried += 1;
+        let outcome_a = cases::run(new_a().as_mut(), &input, max_steps);
+        let outcome_b = cases::run(new_b().as_mut(), &input, max_steps);
+        if matches!(outcome_a, cases::Outcome::Timeout(_))
+            || matches!(outcome_b, cases::Outcome::Timeout(_))
+        {
+            timeouts.push((input, outcome_a, outcome_b));
+            continue;
+        }
+        // compare as printed, so a TM writing `true` agrees with an accepting PDA
+        if outcome_a.to_string() != outcome_b.to_string() {
+            println!("differ on {}", show(&input));
+            println!("  {}: {}", a, show(&outcome_a.to_string()));
+            println!("  {}: {}", b, show(&outcome_b.to_string()));
+            std::process::exit(1);
+        }
+    }
+    println!(
+        "agree on {} of {} inputs up to length {}",
+        tried - timeouts.len(),
+        tried,
+        max_len
+    );
+    if !timeouts.is_empty() {
+        println!(
+            "{} inputs gave no verdict within {} steps:",
+            timeouts.len(),
+            max_steps
+        );
+        for (input, outcome_a, outcome_b) in timeouts.iter().take(10) {
+            println!(
+                "  {}: {} / {}",
+                show(input),
+                show(&outcome_a.to_string()),
+                show(&outcome_b.to_string())
+            );
+        }
+        if timeouts.len() > 10 {
+            println!("  ... and {} more", timeouts.len() - 10);
+        }
+    }
+}
+
+fn lang(
+    path: &str,
+    max_len: usize,
+    max_steps: usize,
+    dialect: Option<automata::tm::Dialect>,
+    max_errors: Option<usize>,
+) {
+    if let Mode::Dfa = mode(path) {
+        let dfa = load_dfa(&read_program(path), max_errors);
+        for w in analysis::dfa_accepted(&dfa, max_len) {
+            println!("{}", show(&w));
+        }
+        return;
     }
 
+    let (new, symbols) = machine(path, dialect, max_errors);
+    let mut timeouts = 0;
+    for input in cases::shortlex(&symbols, max_len) {
+        match cases::run(new().as_mut(), &input, max_steps) {
+            cases::Outcome::Verdict(true) => println!("{}", show(&input)),
+            cases::Outcome::Output(s) => println!("{} => {}", show(&input), show(&s)),
+            cases::Outcome::Timeout(_) => timeouts += 1,
+            _ => (),
+        }
+    }
+    if timeouts > 0 {
+        eprintln!(
+            "note: {} inputs gave no verdict within {} steps",
+            timeouts, max_steps
+        );
+    }
+}
+
+fn dot(
+    path: &str,
+    input: Option<&str>,
+    max_steps: usize,
+    dialect: Option<automata::tm::Dialect>,
+    max_errors: Option<usize>,
+) {
+    let program = read_program(path);
+    let graph = match mode(path) {
+        Mode::Dfa => dot::dfa(&load_dfa(&program, max_errors)),
+        Mode::Pda => dot::pda(&load_pda(&program, max_errors)),
+        Mode::Tm => dot::tm(&load_tm(&program, dialect, max_errors)),
+    };
+
+    let mut trail = Vec::new();
+    if let Some(input) = input {
+        let mut arch_state = start(path, input, true, dialect, max_errors);
+        while trail.len() < max_steps {
+            let from = arch_state.state().to_owned();
+            if arch_state.step().halted() {
+                break;
+            }
+            trail.push((from, arch_state.state().to_owned()));
+        }
+    }
+    print!("{}", graph.render(&trail));
+}
+
+/// Load a machine and feed it the input, or report the error and exit.
+fn start(
+    path: &str,
+    input: &str,
+    verbose: bool,
+    dialect: Option<automata::tm::Dialect>,
+    max_errors: Option<usize>,
+) -> Box<dyn automata::ArchState> {
+    let program = read_program(path);
+
+    let banner_err = banner("ERR");
+    let banner_end = banner("END");
+
+    let mut arch_state: Box<dyn automata::ArchState> = match mode(path) {
+        Mode::Dfa => Box::new(automata::DfaArchState::new(load_dfa(&program, max_errors))),
+        Mode::Pda => Box::new(automata::PdaArchState::new(load_pda(&program, max_errors))),
+        Mode::Tm => Box::new(automata::TmArchState::new(load_tm(
+            &program, dialect, max_errors,
+        ))),
+    };
+
     let verbose_input_err = |col: usize| {
         eprintln!("{}", banner_err);
         eprintln!(
             "error: '{}' was not declared in the ser of input symbols",
-            args.input.chars().nth(col).unwrap()
+            input.chars().nth(col).unwrap()
         );
-        eprintln!("Input: {}", args.input);
+        eprintln!("Input: {}", input);
         eprintln!("       {}^", " ".repeat(col));
         eprintln!("{}", banner_end);
     };
 
-    match arch_state.input(&args.input) {
-        Ok(_) => (),
-        Err(e) => match e {
-            automata::Exception::Dfa(e) => {}
-            automata::Exception::Pda(e) => match e {
-                automata::pda::Exception::InvalidInput { col } => {
-                    if args.verbose {
-                        verbose_input_err(col);
-                    } else {
-                        eprintln!("Illegal Input");
-                    }
-                    std::process::exit(1);
-                }
-                _ => panic!(),
-            },
-            automata::Exception::Tm(e) => match e {
-                automata::tm::Exception::InvalidInput { input: _, offset } => {
-                    verbose_input_err(offset);
-                    std::process::exit(1);
-                }
-                _ => panic!(),
-            },
-        },
+    if let Err(automata::Error::InvalidInput { col, .. }) = arch_state.input(input) {
+        // a TM reports it in full even when not verbose
+        if verbose || matches!(mode(path), Mode::Tm) {
+            verbose_input_err(col);
+        } else {
+            eprintln!("Illegal Input");
+        }
+        std::process::exit(1);
     }
 
-    if args.verbose {
+    arch_state
+}
+
+/// Run every line of `inputs_path` through the machine, parsed once, an empty line being
+/// the empty input.
+fn batch(
+    path: &str,
+    inputs_path: &str,
+    dialect: Option<automata::tm::Dialect>,
+    max_errors: Option<usize>,
+) {
+    let (new, _) = machine(path, dialect, max_errors);
+    let mut inputs = String::new();
+    let read = match inputs_path {
+        "-" => std::io::stdin().read_to_string(&mut inputs),
+        _ => std::fs::File::open(inputs_path).and_then(|mut f| f.read_to_string(&mut inputs)),
+    };
+    if let Err(e) = read {
+        eprintln!("error: failed to read inputs: {}", e);
+        std::process::exit(1);
+    }
+    for input in inputs.lines() {
+        // unbounded, like a single run
+        println!("{}", cases::run(new().as_mut(), input, usize::MAX));
+    }
+}
+
+fn run(
+    path: &str,
+    input: &str,
+    verbose: bool,
+    format: trace::TraceFormat,
+    dialect: Option<automata::tm::Dialect>,
+    max_errors: Option<usize>,
+) {
+    if format == trace::TraceFormat::Json {
+        let (new, _) = machine(path, dialect, max_errors);
+        let mut arch_state = new();
+        if let Err(e) = arch_state.input(input) {
+            println!("{}", trace::illegal(&e));
+            std::process::exit(1);
+        }
+        let outcome = automata::run(arch_state.as_mut(), None, |arch_state| {
+            println!("{}", trace::step(arch_state))
+        });
+        println!("{}", trace::verdict(&outcome));
+        std::process::exit(0);
+    }
+
+    let banner_run = banner("RUN");
+    let banner_end = banner("END");
+    let banner_split = "-".repeat(45);
+
+    if verbose {
+        println!("Input: {}", input);
+    }
+
+    let mut arch_state = start(path, input, verbose, dialect, max_errors);
+
+    if verbose {
         println!("{}", banner_run);
     }
 
-    loop {
-        if args.verbose {
+    let outcome = automata::run(arch_state.as_mut(), None, |arch_state| {
+        if verbose {
             print!("{}", arch_state);
             println!("{}", banner_split);
         }
-        match arch_state.step() {
-            Ok(_) => (),
-            Err(e) => {
-                match e {
-                    automata::Exception::Dfa(_e) => panic!(),
-                    automata::Exception::Pda(e) => match e {
-                        automata::pda::Exception::Accept => {
-                            println!("true");
-                        }
-                        automata::pda::Exception::Reject => {
-                            println!("false");
-                        }
-                        _ => panic!(),
-                    },
-                    automata::Exception::Tm(e) => match e {
-                        automata::tm::Exception::Reject(s) | automata::tm::Exception::Accept(s) => {
-                            if args.verbose {
-                                println!("Result: {}", s);
-                            } else {
-                                println!("{}", s);
-                            }
-                        }
-                        _ => panic!(),
-                    },
-                }
-                if args.verbose {
-                    println!("{}", banner_end);
-                }
-                std::process::exit(0);
-            }
-        }
+    });
+    match outcome.output() {
+        Some(s) if verbose => println!("Result: {}", s),
+        Some(s) => println!("{}", s),
+        None => println!("{}", outcome.accepted()),
+    }
+    if verbose {
+        println!("{}", banner_end);
     }
+    std::process::exit(0);
 }
@@ -1,9 +1,12 @@
-use std::collections::{HashMap, HashSet};
+use std::collections::HashMap;
+
+use crate::cases::Case;
 
 #[derive(Debug)]
-pub enum Value {
+pub(crate) enum Value {
     Str(String),
-    Set(HashSet<String>),
+    /// members in source order, duplicates included
+    Set(Vec<(Position, String)>),
 }
 
 impl Default for Value {
@@ -12,12 +15,18 @@ impl Default for Value {
     }
 }
 
-pub type KVStore = HashMap<String, (Position, Value)>;
+pub(crate) type KVStore = HashMap<String, (Position, Value)>;
 
 #[derive(Default)]
-pub struct Config {
+pub(crate) struct Config {
+    /// each declaration with the position of its value
     pub store: KVStore,
     pub trans: Vec<(Position, Vec<String>)>,
+    /// declarations whose value did not parse, they are not in `store`
+    pub broken: Vec<String>,
+    /// the `#TEST input => expected` lines, no part of the machine
+    pub tests: Vec<Case>,
+    pub warnings: Vec<(Position, Warning)>,
 }
 
 #[derive(Default, Debug, Clone)]
@@ -26,12 +35,91 @@ pub enum ParseErrorKV {
     Unknown,
     Str,
     Set,
+    /// a declaration seen before, at the position of its first `#name`
+    Duplicate(String, Position),
 }
 
 #[derive(Debug, Clone)]
 pub enum ParseError {
     KV(ParseErrorKV),
     Trans,
+    /// a test case that is not `input => expected`
+    Case,
+}
+
+/// An error with a stable code, `E01xx` for syntax, `E02xx` for declarations
+/// and `E03xx` for transitions.
+pub trait Diagnostic: std::fmt::Display {
+    fn code(&self) -> &'static str;
+}
+
+impl std::fmt::Display for ParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ParseError::KV(ParseErrorKV::Unknown) => {
+                write!(f, "expected a declaration like `#Q = {{q0,q1}}`")
+            }
+            ParseError::KV(ParseErrorKV::Str) => write!(
+                f,
+                "unexpected character, a value is made of letters, digits and '_'"
+            ),
+            ParseError::KV(ParseErrorKV::Set) => write!(
+                f,
+                "invalid set member, members are printable and cannot contain ',', ';', '{{' or '}}'"
+            ),
+            ParseError::KV(ParseErrorKV::Duplicate(k, first)) => write!(
+                f,
+                "#{} is declared again, first at line {}, col {}",
+                k,
+                first.line(),
+                first.col + 1
+            ),
+            ParseError::Trans => write!(f, "wrong number of fields in this transition"),
+            ParseError::Case => write!(f, "expected a test case like `aabb => true`"),
+        }
+    }
+}
+
+impl Diagnostic for ParseError {
+    fn code(&self) -> &'static str {
+        match self {
+            ParseError::KV(ParseErrorKV::Unknown) => "E0101",
+            ParseError::KV(ParseErrorKV::Str) => "E0102",
+            ParseError::KV(ParseErrorKV::Set) => "E0103",
+            ParseError::KV(ParseErrorKV::Duplicate(..)) => "E0105",
+            ParseError::Trans => "E0104",
+            ParseError::Case => "E0106",
+        }
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+/// Something written twice, harmless to the machine but likely a mistake.
+#[derive(Debug, Clone)]
+pub(crate) enum Warning {
+    /// (declaration, member, first occurrence)
+    DuplicateItem(String, String, Position),
+    /// the first rule written the same way
+    DuplicateRule(Position),
+}
+
+impl std::fmt::Display for Warning {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Warning::DuplicateItem(k, item, first) => write!(
+                f,
+                "'{}' is declared more than once in #{} (first at line {}, col {})",
+                item,
+                k,
+                first.line(),
+                first.col + 1
+            ),
+            Warning::DuplicateRule(first) => {
+                write!(f, "rule repeats the one at line {}", first.line())
+            }
+        }
+    }
 }
 
 #[derive(Clone, Debug, Default)]
@@ -39,41 +127,163 @@ pub struct Position {
     pub inst: String,
     pub row: usize,
     pub col: usize,
+    /// width of the token at `col`, in bytes
+    pub len: usize,
+}
+
+impl Position {
+    /// 1-based line number, as editors count them.
+    pub fn line(&self) -> usize {
+        self.row + 1
+    }
+
+    /// The `#name` of the declaration on this line.
+    pub(crate) fn key(&self) -> Position {
+        let col = self.inst.find('#').unwrap_or(0);
+        let len = self.inst[col..]
+            .find('=')
+            .map_or(0, |eq| self.inst[col..col + eq].trim_end().len());
+        Position {
+            col,
+            len,
+            ..self.clone()
+        }
+    }
+
+    /// The `k`th field of the transition on this line.
+    pub(crate) fn field(&self, k: usize) -> Position {
+        let code = self
+            .inst
+            .split_once(';')
+            .map_or(self.inst.as_str(), |(code, _)| code);
+        match fields(code).into_iter().nth(k) {
+            Some((span, _)) => Position {
+                col: span.start,
+                len: span.len(),
+                ..self.clone()
+            },
+            None => self.clone(),
+        }
+    }
+
+    /// The first `ch` in the fields `ks` of the transition on this line, or the first of the
+    /// fields if there is none.
+    pub(crate) fn symbol(&self, ks: &[usize], ch: char) -> Position {
+        for k in ks {
+            let field = self.field(*k);
+            if let Some(i) = self.inst[field.col..field.col + field.len].find(ch) {
+                return Position {
+                    col: field.col + i,
+                    len: ch.len_utf8(),
+                    ..field
+                };
+            }
+        }
+        self.field(ks[0])
+    }
+}
+
+/// Where the declarations, set members and rules of a machine came from.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct SourceMap {
+    pub decls: HashMap<String, Position>,
+    /// members of each set declaration, in source order, duplicates included
+    pub items: HashMap<String, Vec<(Position, String)>>,
+    /// `rules[i]` is the transition `delta[i]` was built from
+    pub rules: Vec<Position>,
+    /// what the parser warned about
+    pub warnings: Vec<(Position, Warning)>,
+}
+
+impl SourceMap {
+    pub(crate) fn new(c: &Config) -> Self {
+        let mut src = SourceMap {
+            warnings: c.warnings.clone(),
+            ..Default::default()
+        };
+        for (k, (pos, v)) in c.store.iter() {
+            src.decls.insert(k.to_owned(), pos.clone());
+            if let Value::Set(items) = v {
+                src.items.insert(k.to_owned(), items.clone());
+            }
+        }
+        src
+    }
+
+    /// Position of the first occurrence of `item` in set declaration `key`.
+    pub(crate) fn item(&self, key: &str, item: &str) -> Position {
+        self.items
+            .get(key)
+            .and_then(|items| items.iter().find(|(_, s)| s == item))
+            .map(|(pos, _)| pos.clone())
+            .unwrap_or_default()
+    }
 }
 
+/// The line with carets under the token, nothing for a position outside the source.
 impl std::fmt::Display for Position {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        writeln!(f, "line {}, col {}:", self.row, self.col)?;
-        writeln!(f, "{}", self.inst)?;
-        writeln!(f, "{}", " ".repeat(self.col) + "^")
+        if self.inst.is_empty() {
+            return Ok(());
+        }
+        let line = self.line().to_string();
+        let margin = " ".repeat(line.len());
+        // widen a span that starts or ends inside a character to whole characters
+        let mut start = self.col.min(self.inst.len());
+        while !self.inst.is_char_boundary(start) {
+            start -= 1;
+        }
+        let mut end = (self.col + self.len).clamp(start, self.inst.len());
+        while !self.inst.is_char_boundary(end) {
+            end += 1;
+        }
+        let indent: String = self.inst[..start]
+            .chars()
+            .map(|ch| if ch == '\t' { '\t' } else { ' ' })
+            .collect();
+        let width = self.inst[start..end].chars().count();
+        let col = self.inst[..start].chars().count() + 1;
+        writeln!(f, "{}--> line {}, col {}", margin, line, col)?;
+        writeln!(f, "{} |", margin)?;
+        writeln!(f, "{} | {}", line, self.inst)?;
+        writeln!(f, "{} | {}{}", margin, indent, "^".repeat(width.max(1)))
     }
 }
 
-fn parse_set(line: &str) -> Result<HashSet<String>, (bool, usize)> /* Err(confident, col) */ {
+/// Byte offset of `sub` inside `s`, `sub` must be a subslice of `s`.
+fn offset(s: &str, sub: &str) -> usize {
+    sub.as_ptr() as usize - s.as_ptr() as usize
+}
+
+fn parse_set(line: &str) -> Result<Vec<(usize, String)>, (bool, usize, usize)> /* Err(confident, col, len) */
+{
     fn valid_item_char(c: char) -> bool {
         c.is_ascii_graphic() && ![',', ';', '{', '}'].contains(&c)
     }
 
     let s = line.trim();
     if s.is_empty() {
-        return Err((false, 0));
+        return Err((false, 0, 0));
     }
 
     if !s.starts_with('{') || !s.ends_with('}') {
-        return Err((false, 0));
+        return Err((false, 0, 0));
     }
 
-    let items: HashSet<String> = s
+    let items: Vec<(usize, String)> = s
         .strip_prefix('{')
         .unwrap()
         .strip_suffix('}')
         .unwrap()
         .split(',')
-        .map(|item| item.trim().to_owned())
+        .map(|item| (offset(line, item.trim()), item.trim().to_owned()))
         .collect();
-    match items.iter().all(|item| item.chars().all(valid_item_char)) {
-        true => Ok(items),
-        false => Err((true, 0)),
+    match items
+        .iter()
+        .find(|(_, item)| !item.chars().all(valid_item_char))
+    {
+        None => Ok(items),
+        Some((col, item)) => Err((true, *col, item.len())),
     }
 }
 
@@ -88,7 +298,7 @@ fn parse_str(s: &str) -> Result<String, usize> {
         return Err(0);
     }
 
-    for (col, c) in s.chars().enumerate() {
+    for (col, c) in s.char_indices() {
         if !valid_str_char(c) {
             return Err(col);
         }
@@ -96,16 +306,66 @@ fn parse_str(s: &str) -> Result<String, usize> {
     Ok(s.to_owned())
 }
 
+/// Split a transition on whitespace, except inside a symbol class `{...}`,
+/// each field with the byte range it spans.
+pub(crate) fn fields(s: &str) -> Vec<(std::ops::Range<usize>, String)> {
+    let mut segs = Vec::new();
+    let mut seg = String::new();
+    let mut span = 0..0;
+    let mut depth = 0usize;
+    for (i, ch) in s.char_indices() {
+        match ch {
+            '{' => depth += 1,
+            '}' => depth = depth.saturating_sub(1),
+            _ => (),
+        }
+        if !ch.is_whitespace() {
+            if seg.is_empty() {
+                span.start = i;
+            }
+            seg.push(ch);
+            span.end = i + ch.len_utf8();
+        } else if depth == 0 && !seg.is_empty() {
+            segs.push((span.clone(), std::mem::take(&mut seg)));
+        }
+    }
+    if !seg.is_empty() {
+        segs.push((span, seg));
+    }
+    segs
+}
+
 fn parse_trans(s: &str, nr_trans_item: usize) -> Option<Vec<String>> {
-    let segs: Vec<_> = s.split_whitespace().map(|s| s.to_owned()).collect();
+    let segs = fields(s);
     if segs.len() != nr_trans_item {
         return None;
     }
-    Some(segs)
+    Some(segs.into_iter().map(|(_, seg)| seg).collect())
+}
+
+/// Split a test case `input => expected`, the input may be empty but has no spaces.
+pub(crate) fn parse_case(s: &str) -> Option<(String, String)> {
+    let (input, expected) = s.split_once("=>")?;
+    let input = input.trim();
+    if input.contains(char::is_whitespace) {
+        return None;
+    }
+    Some((input.to_owned(), expected.trim().to_owned()))
 }
 
-pub fn parse(s: &str, nr_trans_item: usize) -> Result<Config, (Position, ParseError)> {
+pub(crate) fn parse(s: &str, nr_trans_item: usize) -> Result<Config, Vec<(Position, ParseError)>> {
+    match parse_all(s, nr_trans_item) {
+        (c, errors) if errors.is_empty() => Ok(c),
+        (_, errors) => Err(errors),
+    }
+}
+
+/// Parse what can be parsed, skipping the lines with errors.
+pub(crate) fn parse_all(s: &str, nr_trans_item: usize) -> (Config, Vec<(Position, ParseError)>) {
     let mut c = Config::default();
+    let mut errors = Vec::new();
+    // the `#name` of every declaration so far, broken ones included
+    let mut declared: HashMap<String, Position> = HashMap::new();
     for (row, line) in s.lines().enumerate() {
         let inst = match line.split_once(';') {
             Some((code, _)) => code,
@@ -125,65 +385,221 @@ pub fn parse(s: &str, nr_trans_item: usize) -> Result<Config, (Position, ParseEr
         if inst.starts_with('#') {
             // KVStore
             let inst = inst.strip_prefix('#').unwrap();
+            if let Some(case) = inst
+                .strip_prefix("TEST")
+                .filter(|case| case.starts_with(char::is_whitespace))
+            {
+                let pos = Position {
+                    col: offset(line, inst) - 1,
+                    len: inst.len() + 1,
+                    ..pos
+                };
+                match parse_case(case) {
+                    Some((input, expected)) => c.tests.push(Case { input, expected }),
+                    None => errors.push((pos, ParseError::Case)),
+                }
+                continue;
+            }
             if let Some((ks, vs)) = inst.split_once('=') {
-                let margin = ks.len() + 2;
+                let margin = offset(line, vs.trim_start());
                 let ks = ks.trim();
                 let vs = vs.trim();
+                if let Some(first) = declared.get(ks) {
+                    let e = ParseErrorKV::Duplicate(ks.to_owned(), first.clone());
+                    errors.push((pos.key(), ParseError::KV(e)));
+                    continue;
+                }
+                declared.insert(ks.to_owned(), pos.key());
+                let value = Position {
+                    col: margin,
+                    len: vs.len(),
+                    ..pos.clone()
+                };
                 match parse_set(vs) {
                     Ok(s) => {
-                        c.store.insert(ks.to_owned(), (pos, Value::Set(s)));
+                        let items: Vec<(Position, String)> = s
+                            .into_iter()
+                            .map(|(off, item)| {
+                                let pos = Position {
+                                    col: margin + off,
+                                    len: item.len(),
+                                    ..pos.clone()
+                                };
+                                (pos, item)
+                            })
+                            .collect();
+                        for (i, (at, item)) in items.iter().enumerate() {
+                            if let Some((first, _)) = items[..i].iter().find(|(_, s)| s == item) {
+                                let w = Warning::DuplicateItem(
+                                    ks.to_owned(),
+                                    item.to_owned(),
+                                    first.clone(),
+                                );
+                                c.warnings.push((at.clone(), w));
+                            }
+                        }
+                        c.store.insert(ks.to_owned(), (value, Value::Set(items)));
                         continue;
                     }
-                    Err((confident, off)) => {
+                    Err((confident, off, len)) => {
                         if confident {
-                            return Err((
-                                Position {
-                                    col: margin + off,
-                                    ..pos
-                                },
-                                ParseError::KV(ParseErrorKV::Set),
-                            ));
+                            let pos = Position {
+                                col: margin + off,
+                                len,
+                                ..pos
+                            };
+                            errors.push((pos, ParseError::KV(ParseErrorKV::Set)));
+                            c.broken.push(ks.to_owned());
+                            continue;
                         }
                     }
                 }
                 match parse_str(vs) {
                     Ok(s) => {
-                        c.store.insert(ks.to_owned(), (pos, Value::Str(s)));
+                        c.store.insert(ks.to_owned(), (value, Value::Str(s)));
                         continue;
                     }
                     Err(off) => {
-                        return Err((
-                            Position {
-                                col: margin + off,
-                                ..pos
-                            },
-                            ParseError::KV(ParseErrorKV::Str),
-                        ))
+                        let pos = Position {
+                            col: margin + off,
+                            len: vs[off..].chars().next().map_or(0, char::len_utf8),
+                            ..pos
+                        };
+                        errors.push((pos, ParseError::KV(ParseErrorKV::Str)));
+                        c.broken.push(ks.to_owned());
                     }
                 }
             } else {
-                return Err((
-                    Position { col: 3, ..pos },
-                    ParseError::KV(ParseErrorKV::Unknown),
-                ));
+                let pos = Position {
+                    col: offset(line, inst) - 1,
+                    len: inst.len() + 1,
+                    ..pos
+                };
+                errors.push((pos, ParseError::KV(ParseErrorKV::Unknown)));
             }
         } else {
             let t = match parse_trans(inst, nr_trans_item) {
-                None => return Err((pos, ParseError::Trans)),
+                None => {
+                    let pos = Position {
+                        col: offset(line, inst),
+                        len: inst.len(),
+                        ..pos
+                    };
+                    errors.push((pos, ParseError::Trans));
+                    continue;
+                }
                 Some(t) => t,
             };
             // Transition
+            let pos = Position {
+                col: offset(line, inst),
+                len: inst.len(),
+                ..pos
+            };
+            if let Some((first, _)) = c.trans.iter().find(|(_, u)| *u == t) {
+                c.warnings
+                    .push((pos.clone(), Warning::DuplicateRule(first.clone())));
+            }
             c.trans.push((pos, t))
         }
     }
 
-    Ok(c)
+    (c, errors)
 }
 
-pub fn valid_state_char(c: char) -> bool {
+pub(crate) fn valid_state_char(c: char) -> bool {
     c.is_ascii_alphanumeric() || c == '_'
 }
 
-pub fn valid_symbol_char(c: char) -> bool {
+pub(crate) fn valid_symbol_char(c: char) -> bool {
     c.is_ascii_graphic() && ![' ', ',', ';', '{', '}', '*'].contains(&c)
 }
+
+/// The `required` declarations missing from `c`, sorted, and those that are neither
+/// required nor `optional`, with the position of their names.
+pub(crate) fn check_decls(
+    c: &Config,
+    required: &[&str],
+    optional: &[&str],
+) -> (Vec<String>, Vec<(Position, String)>) {
+    let mut missing: Vec<String> = required
+        .iter()
+        .filter(|k| !c.store.contains_key(**k) && !c.broken.iter().any(|b| b == *k))
+        .map(|k| (*k).to_owned())
+        .collect();
+    missing.sort();
+    let unknown = c
+        .store
+        .iter()
+        .filter(|(k, _)| !required.contains(&k.as_str()) && !optional.contains(&k.as_str()))
+        .map(|(k, (pos, _))| (pos.key(), k.to_owned()))
+        .collect();
+    (missing, unknown)
+}
+
+/// Sort errors by where they are, those about the whole file first.
+pub(crate) fn sorted<E>(mut errors: Vec<(Position, E)>) -> Vec<(Position, E)> {
+    errors.sort_by_key(|(pos, _)| (!pos.inst.is_empty(), pos.row, pos.col));
+    errors
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lines() {
+        let program = "\
+#Q = {q}
+#TEST ab => true
+#TEST  => false ; the empty input
+#TEST a b => true
+#TESTS = {a}
+";
+        let (c, errors) = parse_all(program, 5);
+        let cases: Vec<(&str, &str)> = c
+            .tests
+            .iter()
+            .map(|case| (case.input.as_str(), case.expected.as_str()))
+            .collect();
+        assert_eq!(cases, [("ab", "true"), ("", "false")]);
+        // tests are no declarations, `#TESTS` is one
+        assert!(!c.store.contains_key("TEST"));
+        assert!(c.store.contains_key("TESTS"));
+        let found: Vec<(usize, usize, &str)> = errors
+            .iter()
+            .map(|(pos, e)| (pos.line(), pos.col + 1, e.code()))
+            .collect();
+        assert_eq!(found, [(4, 1, "E0106")]);
+    }
+
+    #[test]
+    fn repeats_are_warnings() {
+        let program = "\
+#G = {a, b,a}
+q a b r p
+q  a b r p ; again
+q a a r p
+";
+        let (c, errors) = parse_all(program, 5);
+        assert!(errors.is_empty());
+        let found: Vec<(usize, usize, String)> = c
+            .warnings
+            .iter()
+            .map(|(pos, w)| (pos.line(), pos.col + 1, w.to_string()))
+            .collect();
+        assert_eq!(
+            found,
+            [
+                (
+                    1,
+                    12,
+                    "'a' is declared more than once in #G (first at line 1, col 7)".to_owned()
+                ),
+                (3, 1, "rule repeats the one at line 2".to_owned()),
+            ]
+        );
+        // repeats stay, the machine decides what they mean
+        assert_eq!(c.trans.len(), 3);
+    }
+}
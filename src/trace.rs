@@ -0,0 +1,160 @@
+use serde_json::json;
+
+use crate::automata::{ArchState, Error, StepOutcome, View};
+
+#[derive(clap::ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum TraceFormat {
+    /// the step by step trace of `--verbose`
+    #[default]
+    Human,
+    /// one JSON object per line, for scripts
+    Json,
+}
+
+/// The configuration after `arch.steps()` steps, as one JSON line.
+pub fn step(arch: &dyn ArchState) -> String {
+    let mut record = json!({
+        "step": arch.steps(),
+        "state": arch.state(),
+        "rule": arch.rule().map(|pos| json!({
+            "line": pos.row + 1,
+            "text": pos.inst.trim(),
+        })),
+    });
+    match arch.view() {
+        View::Dfa { input } => record["input"] = json!(input),
+        View::Pda { input, stack } => {
+            record["input"] = json!(input);
+            record["stack"] = json!(stack);
+        }
+        View::Tm { tapes, .. } => {
+            record["tapes"] = tapes
+                .iter()
+                .map(|tape| {
+                    json!({
+                        "first": tape.first,
+                        "cells": tape.cells.iter().collect::<String>(),
+                        "head": tape.first + tape.head as isize,
+                        "written": tape.written.map(String::from),
+                    })
+                })
+                .collect();
+        }
+    }
+    record.to_string()
+}
+
+/// The final record of a trace, for the outcome of the halting step.
+pub fn verdict(outcome: &StepOutcome) -> String {
+    let verdict = match outcome.accepted() {
+        true => "accept",
+        false => "reject",
+    };
+    let mut record = json!({ "verdict": verdict });
+    if let Some(s) = outcome.output() {
+        record["result"] = json!(s);
+    }
+    if let StepOutcome::Stuck(why) = outcome {
+        record["stuck"] = json!(why.to_string());
+    }
+    record.to_string()
+}
+
+/// The only record of a trace whose input has an undeclared symbol, `col` counts from 1.
+pub fn illegal(e: &Error) -> String {
+    let Error::InvalidInput { symbol, col } = e;
+    json!({ "verdict": "illegal", "symbol": symbol.to_string(), "col": col + 1 }).to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::automata::{PdaArchState, Stuck, TmArchState};
+    use serde_json::Value;
+
+    fn parse(record: String) -> Value {
+        serde_json::from_str(&record).unwrap()
+    }
+
+    #[test]
+    fn pda_steps() {
+        let program = "\
+#Q = {q,acc}
+#S = {a}
+#G = {z,1}
+#q0 = q
+#z0 = z
+#F = {acc}
+
+q a z q 1z
+q _ 1 acc _
+";
+        let mut arch = PdaArchState::new(program.parse().unwrap());
+        arch.input("a").unwrap();
+        assert_eq!(
+            parse(step(&arch)),
+            json!({"step": 0, "state": "q", "rule": null, "input": "a", "stack": "z"})
+        );
+        arch.step();
+        assert_eq!(
+            parse(step(&arch)),
+            json!({
+                "step": 1,
+                "state": "q",
+                "rule": {"line": 8, "text": "q a z q 1z"},
+                "input": "",
+                "stack": "1z",
+            })
+        );
+    }
+
+    #[test]
+    fn tm_steps() {
+        let program = "\
+#Q = {q,acc}
+#S = {a}
+#G = {a,b,_}
+#q0 = q
+#B = _
+#F = {acc}
+#N = 1
+
+q a b l acc
+";
+        let mut arch = TmArchState::new(program.parse().unwrap());
+        arch.input("a").unwrap();
+        arch.step();
+        let record = parse(step(&arch));
+        assert_eq!(
+            record["tapes"],
+            json!([{"first": -1, "cells": "_b", "head": -1, "written": "b"}])
+        );
+        // the machine halts on the step after entering a final state
+        let outcome = arch.step();
+        assert_eq!(
+            parse(verdict(&outcome)),
+            json!({"verdict": "accept", "result": "b"})
+        );
+    }
+
+    #[test]
+    fn verdicts() {
+        let stuck = StepOutcome::Stuck(Stuck::EmptyStack);
+        assert_eq!(
+            parse(verdict(&stuck)),
+            json!({"verdict": "reject", "stuck": "the stack is empty"})
+        );
+        assert_eq!(
+            parse(verdict(&StepOutcome::Accept(None))),
+            json!({"verdict": "accept"})
+        );
+        let e = Error::InvalidInput {
+            symbol: 'c',
+            col: 2,
+        };
+        assert_eq!(
+            parse(illegal(&e)),
+            json!({"verdict": "illegal", "symbol": "c", "col": 3})
+        );
+    }
+}
@@ -0,0 +1,189 @@
+use std::io::{Stdout, Write};
+use std::time::{Duration, Instant};
+
+use crossterm::{
+    cursor,
+    event::{self, Event, KeyCode, KeyEventKind},
+    execute, queue,
+    style::{Attribute, Print, SetAttribute},
+    terminal::{self, ClearType},
+};
+
+use crate::automata::{ArchState, Tape, View};
+
+const KEYS: &str = "space play/pause  s step  b back  +/- speed  q quit";
+
+/// Delays between steps while playing, `+` and `-` move through them.
+const SPEEDS: [u64; 7] = [2000, 1000, 500, 250, 100, 40, 10];
+
+struct Player {
+    arch: Box<dyn ArchState>,
+    playing: bool,
+    speed: usize,
+    /// set once the machine halts
+    verdict: Option<String>,
+}
+
+impl Player {
+    fn step(&mut self) {
+        if self.verdict.is_none() {
+            let outcome = self.arch.step();
+            if outcome.halted() {
+                self.verdict = Some(outcome.to_string());
+                self.playing = false;
+            }
+        }
+    }
+
+    fn back(&mut self) {
+        if self.arch.back() {
+            self.verdict = None;
+        }
+    }
+
+    fn draw(&self, out: &mut Stdout) -> std::io::Result<()> {
+        let (width, height) = terminal::size()?;
+        let width = width as usize;
+        queue!(out, terminal::Clear(ClearType::All), cursor::MoveTo(0, 0))?;
+
+        let mut lines = vec![
+            format!(
+                "Step: {}   State: {}   {} ({} ms/step)",
+                self.arch.steps(),
+                self.arch.state(),
+                if self.playing { "playing" } else { "paused" },
+                SPEEDS[self.speed]
+            ),
+            match self.arch.rule() {
+                Some(pos) => format!("Rule: line {}: {}", pos.line(), pos.inst.trim()),
+                None => "Rule:".to_owned(),
+            },
+            String::new(),
+        ];
+        match self.arch.view() {
+            View::Tm { blank, tapes } => {
+                for (i, tape) in tapes.iter().enumerate() {
+                    lines.extend(tape_lines(i, tape, blank, width));
+                    lines.push(String::new());
+                }
+            }
+            View::Dfa { input } => lines.push(format!("Input: {}", input)),
+            View::Pda { input, stack } => {
+                lines.push(format!("Input: {}", input));
+                lines.push(String::new());
+                // the stack grows upwards, top first
+                let rows = (height as usize).saturating_sub(lines.len() + 5);
+                let shown = stack.chars().count().min(rows);
+                for (k, ch) in stack.chars().take(shown).enumerate() {
+                    let label = if k == 0 { " <- top" } else { "" };
+                    lines.push(format!("  | {} |{}", ch, label));
+                }
+                if shown < stack.chars().count() {
+                    lines.push(format!("  | . | {} more", stack.chars().count() - shown));
+                }
+                lines.push("  +---+".to_owned());
+            }
+        }
+        if let Some(v) = &self.verdict {
+            lines.push(format!("Halted: {}", v));
+        }
+
+        for (row, line) in lines.iter().enumerate() {
+            queue!(out, cursor::MoveTo(0, row as u16))?;
+            if row == 0 {
+                queue!(out, SetAttribute(Attribute::Bold))?;
+            }
+            queue!(
+                out,
+                Print(line.chars().take(width).collect::<String>()),
+                SetAttribute(Attribute::Reset)
+            )?;
+        }
+        queue!(
+            out,
+            cursor::MoveTo(0, height.saturating_sub(1)),
+            SetAttribute(Attribute::Reverse),
+            Print(KEYS.chars().take(width).collect::<String>()),
+            SetAttribute(Attribute::Reset)
+        )?;
+        out.flush()
+    }
+}
+
+/// Index, cell and head rows of a tape, in a window of `width` columns centred on the head.
+fn tape_lines(i: usize, tape: &Tape, blank: char, width: usize) -> [String; 3] {
+    let label = format!("Tape{:<3}: ", i);
+    let cells = (width.saturating_sub(label.len()) / 4).max(1) as isize;
+    let head = tape.first + tape.head as isize;
+    let lo = head - cells / 2;
+
+    let mut index = format!("Index{:<2}: ", i);
+    let mut row = label;
+    let mut marker = format!("Head{:<3}: ", i);
+    for idx in lo..lo + cells {
+        let ch = usize::try_from(idx - tape.first)
+            .ok()
+            .and_then(|off| tape.cells.get(off))
+            .unwrap_or(&blank);
+        index += &format!("{:<4}", idx.abs());
+        row += &format!("[{}] ", ch);
+        marker += if idx == head { " ^  " } else { "    " };
+    }
+    [index, row, marker]
+}
+
+/// Animate `arch`, whose input is already set, until the user quits.
+pub fn show(mut arch: Box<dyn ArchState>) -> std::io::Result<()> {
+    arch.record();
+    let mut player = Player {
+        arch,
+        playing: false,
+        speed: 2,
+        verdict: None,
+    };
+
+    let mut out = std::io::stdout();
+    terminal::enable_raw_mode()?;
+    execute!(out, terminal::EnterAlternateScreen, cursor::Hide)?;
+    let result = (|| {
+        let mut last = Instant::now();
+        loop {
+            player.draw(&mut out)?;
+            let delay = Duration::from_millis(SPEEDS[player.speed]);
+            let timeout = match player.playing {
+                true => delay.saturating_sub(last.elapsed()),
+                false => Duration::from_secs(60),
+            };
+            if event::poll(timeout)? {
+                let Event::Key(key) = event::read()? else {
+                    continue;
+                };
+                if key.kind != KeyEventKind::Press {
+                    continue;
+                }
+                match key.code {
+                    KeyCode::Char('q') | KeyCode::Esc => return Ok(()),
+                    KeyCode::Char(' ') => {
+                        player.playing = !player.playing && player.verdict.is_none();
+                        last = Instant::now();
+                    }
+                    KeyCode::Char('s') | KeyCode::Right => player.step(),
+                    KeyCode::Char('b') | KeyCode::Left => player.back(),
+                    KeyCode::Char('+') | KeyCode::Char('=') | KeyCode::Up => {
+                        player.speed = (player.speed + 1).min(SPEEDS.len() - 1)
+                    }
+                    KeyCode::Char('-') | KeyCode::Down => {
+                        player.speed = player.speed.saturating_sub(1)
+                    }
+                    _ => (),
+                }
+            } else if player.playing {
+                player.step();
+                last = Instant::now();
+            }
+        }
+    })();
+    execute!(out, cursor::Show, terminal::LeaveAlternateScreen)?;
+    terminal::disable_raw_mode()?;
+    result
+}